@@ -0,0 +1,49 @@
+//! Lexes generated programs of increasing size and reports the time spent per
+//! line, which stays flat when lexing is linear in the length of the input.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use std::time::{Duration, Instant};
+
+use rust::lexer::Lexer;
+
+const SIZES: [usize; 4] = [6_250, 12_500, 25_000, 50_000];
+const RUNS: u32 = 5;
+
+fn program(lines: usize) -> String {
+    let mut s = String::from("fn main() {\n");
+    for i in 0..lines {
+        s += &format!("    a{} = (a + {}) * b / 3; // line {}\n", i % 10, i, i);
+    }
+    s += "}\n";
+
+    s
+}
+
+fn lex(input: &str) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let count = Lexer::new(input).count();
+        best = best.min(start.elapsed());
+        assert!(count > 0);
+    }
+
+    best
+}
+
+fn main() {
+    let mut per_line = Vec::new();
+    for lines in SIZES {
+        let elapsed = lex(&program(lines));
+        let ns = elapsed.as_nanos() as f64 / lines as f64;
+        println!("{:>6} lines: {:>10.3?} ({:.0} ns/line)", lines, elapsed, ns);
+        per_line.push(ns);
+    }
+
+    // Quadratic lexing would make the largest input 8x slower per line than
+    // the smallest; allow some noise but nothing close to that.
+    let ratio = per_line[per_line.len() - 1] / per_line[0];
+    println!("scaling: {:.2}x per line from smallest to largest", ratio);
+    assert!(ratio < 3.0, "lexing does not scale linearly");
+}
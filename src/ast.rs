@@ -1,11 +1,30 @@
 use std::fmt;
 
-#[derive(PartialEq)]
+use super::json::Json;
+use super::token;
+
+fn span_json(span: token::Span) -> Json {
+    Json::object(vec![
+        ("start", Json::Number(span.start as i128)),
+        ("end", Json::Number(span.end as i128)),
+        ("line", Json::Number(span.line as i128)),
+        ("column", Json::Number(span.column as i128)),
+    ])
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Program {
     pub functions: Vec<Function>,
 }
 
 impl Program {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![(
+            "functions",
+            Json::Array(self.functions.iter().map(|func| func.to_json()).collect()),
+        )])
+    }
+
     // pub fn need_next(&self) -> bool {
     //     if let Some(stmt) = self.statements.last() {
     //         return stmt.need_next();
@@ -26,11 +45,27 @@ impl fmt::Display for Program {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Function {
     pub name: String,
     pub parameters: Vec<String>,
     pub body: Statement,
+    pub span: token::Span,
+}
+
+impl Function {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("kind", Json::string("Function")),
+            ("name", Json::string(&self.name)),
+            (
+                "parameters",
+                Json::Array(self.parameters.iter().map(|p| Json::string(p)).collect()),
+            ),
+            ("body", self.body.to_json()),
+            ("span", span_json(self.span)),
+        ])
+    }
 }
 
 impl fmt::Display for Function {
@@ -45,15 +80,129 @@ impl fmt::Display for Function {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Statement {
-    // Let { name: Expression, value: Expression },
-    Return { return_value: Expression },
-    Expression { expression: Expression },
-    Block { statements: Vec<Statement> },
+    Let {
+        name: String,
+        mutable: bool,
+        value: Expression,
+        span: token::Span,
+    },
+    Return {
+        return_value: Expression,
+        span: token::Span,
+    },
+    /// Leaves the innermost loop, or the loop with `label` such as
+    /// `'outer`.
+    Break {
+        label: Option<String>,
+        span: token::Span,
+    },
+    /// Starts the next iteration of the innermost loop, or of the loop with
+    /// `label`.
+    Continue {
+        label: Option<String>,
+        span: token::Span,
+    },
+    Expression {
+        expression: Expression,
+        /// Whether the expression is followed by `;`. The last statement of
+        /// a function body without one is the function's value.
+        semicolon: bool,
+        span: token::Span,
+    },
+    Block {
+        statements: Vec<Statement>,
+        span: token::Span,
+    },
 }
 
 impl Statement {
+    pub fn span(&self) -> token::Span {
+        match self {
+            Statement::Let { span, .. } => *span,
+            Statement::Return { span, .. } => *span,
+            Statement::Break { span, .. } => *span,
+            Statement::Continue { span, .. } => *span,
+            Statement::Expression { span, .. } => *span,
+            Statement::Block { span, .. } => *span,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = match self {
+            Statement::Let {
+                name,
+                mutable,
+                value,
+                ..
+            } => vec![
+                ("kind", Json::string("Let")),
+                ("name", Json::string(name)),
+                ("mutable", Json::Bool(*mutable)),
+                ("value", value.to_json()),
+            ],
+            Statement::Return { return_value, .. } => vec![
+                ("kind", Json::string("Return")),
+                ("return_value", return_value.to_json()),
+            ],
+            Statement::Break { label, .. } => vec![
+                ("kind", Json::string("Break")),
+                ("label", label.as_deref().map_or(Json::Null, Json::string)),
+            ],
+            Statement::Continue { label, .. } => vec![
+                ("kind", Json::string("Continue")),
+                ("label", label.as_deref().map_or(Json::Null, Json::string)),
+            ],
+            Statement::Expression {
+                expression,
+                semicolon,
+                ..
+            } => vec![
+                ("kind", Json::string("Expression")),
+                ("expression", expression.to_json()),
+                ("semicolon", Json::Bool(*semicolon)),
+            ],
+            Statement::Block { statements, .. } => vec![
+                ("kind", Json::string("Block")),
+                (
+                    "statements",
+                    Json::Array(statements.iter().map(|stmt| stmt.to_json()).collect()),
+                ),
+            ],
+        };
+        fields.push(("span", span_json(self.span())));
+
+        Json::object(fields)
+    }
+
+    /// The expression that gives a block its value: a final expression
+    /// statement with no `;` after it. `while` loops and `if` without `else`
+    /// have no value and so are never tail expressions. Like a block with no
+    /// tail expression, both evaluate to 0 where a value is needed; the
+    /// resolver rejects an `if` without `else` whose block has a tail
+    /// expression, since that value could never be used.
+    ///
+    /// An expression statement without `;` is its own tail, which is how the
+    /// `if` after `else if` gives the alternative its value.
+    pub fn tail(&self) -> Option<&Expression> {
+        match self {
+            Statement::Block { statements, .. } => statements.last().and_then(|stmt| stmt.tail()),
+            Statement::Expression {
+                expression,
+                semicolon: false,
+                ..
+            } => match expression {
+                Expression::WhileExpression { .. }
+                | Expression::IfExpression {
+                    alternative: None, ..
+                } => None,
+                expression => Some(expression),
+            },
+            _ => None,
+        }
+    }
+
     // pub fn need_next(&self) -> bool {
     //     match self {
     //         Statement::Let { name: _, value } => value.need_next(),
@@ -69,17 +218,38 @@ impl Statement {
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            // Statement::Let { name, value } => {
-            //     return write!(f, "let {} = {};", name, value)
-            // }
-            Statement::Return { return_value } => {
+            Statement::Let {
+                name,
+                mutable,
+                value,
+                ..
+            } => {
+                let keyword = if *mutable { "let mut" } else { "let" };
+                write!(f, "{} {} = {}", keyword, name, value)
+            }
+            Statement::Return { return_value, .. } => {
                 write!(f, "return {}", return_value)
             }
-            Statement::Expression { expression } => write!(f, "{}", expression),
-            Statement::Block { statements } => {
+            Statement::Break { label, .. } => match label {
+                Some(label) => write!(f, "break {}", label),
+                None => write!(f, "break"),
+            },
+            Statement::Continue { label, .. } => match label {
+                Some(label) => write!(f, "continue {}", label),
+                None => write!(f, "continue"),
+            },
+            Statement::Expression { expression, .. } => write!(f, "{}", expression),
+            Statement::Block { statements, .. } => {
                 let mut s = "".to_string();
                 for stmt in statements {
-                    s += &format!("\t{};\r\n", stmt);
+                    // Only an expression statement may go without `;`, which
+                    // makes it the value of the block.
+                    let semicolon = match stmt {
+                        Statement::Expression { semicolon, .. } => *semicolon,
+                        Statement::Block { .. } => false,
+                        _ => true,
+                    };
+                    s += &format!("\t{}{}\r\n", stmt, if semicolon { ";" } else { "" });
                 }
                 write!(f, "{{\r\n{}}}", s)
             }
@@ -87,33 +257,49 @@ impl fmt::Display for Statement {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     Identifier {
         value: String,
+        span: token::Span,
     },
     IntegerLiteral {
-        value: i64,
+        /// Never larger than `i64::MAX`, except for the operand of a prefix
+        /// minus, which may be `i64::MIN.unsigned_abs()`.
+        value: u64,
+        span: token::Span,
+    },
+    StringLiteral {
+        value: String,
+        span: token::Span,
+    },
+    CharLiteral {
+        value: char,
+        span: token::Span,
     },
-    // StringLiteral {
-    //     value: String,
-    // },
     PrefixExpression {
         operator: String,
         right: Box<Expression>,
+        span: token::Span,
     },
     InfixExpression {
         left: Box<Expression>,
         operator: String,
         right: Box<Expression>,
+        span: token::Span,
     },
     AssignExpression {
         left: Box<Expression>,
+        /// `=`, or a compound operator such as `+=`.
+        operator: String,
         right: Box<Expression>,
+        span: token::Span,
+    },
+    Boolean {
+        value: bool,
+        span: token::Span,
     },
-    // Boolean {
-    //     value: bool,
-    // },
     // ArrayLiteral {
     //     elements: Vec<Expression>,
     // },
@@ -125,10 +311,20 @@ pub enum Expression {
         condition: Box<Expression>,
         consequence: Box<Statement>,
         alternative: Option<Box<Statement>>,
+        span: token::Span,
     },
     WhileExpression {
+        /// Such as `'outer` in `'outer: while (...) { ... }`.
+        label: Option<String>,
         condition: Box<Expression>,
         consequence: Box<Statement>,
+        span: token::Span,
+    },
+    /// A block used as an expression. Its value is that of its tail
+    /// expression, or 0 if it has none.
+    BlockExpression {
+        body: Box<Statement>,
+        span: token::Span,
     },
     // FunctionLiteral {
     //     parameters: Vec<Expression>,
@@ -137,14 +333,129 @@ pub enum Expression {
     CallExpression {
         function: String,
         arguments: Vec<Expression>,
+        span: token::Span,
     },
     // HashLiteral {
     //     pairs: Vec<(Expression, Expression)>,
     // },
-    NeedNext,
 }
 
 impl Expression {
+    pub fn span(&self) -> token::Span {
+        match self {
+            Expression::Identifier { span, .. } => *span,
+            Expression::IntegerLiteral { span, .. } => *span,
+            Expression::StringLiteral { span, .. } => *span,
+            Expression::CharLiteral { span, .. } => *span,
+            Expression::Boolean { span, .. } => *span,
+            Expression::PrefixExpression { span, .. } => *span,
+            Expression::InfixExpression { span, .. } => *span,
+            Expression::AssignExpression { span, .. } => *span,
+            Expression::IfExpression { span, .. } => *span,
+            Expression::WhileExpression { span, .. } => *span,
+            Expression::BlockExpression { span, .. } => *span,
+            Expression::CallExpression { span, .. } => *span,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = match self {
+            Expression::Identifier { value, .. } => vec![
+                ("kind", Json::string("Identifier")),
+                ("value", Json::string(value)),
+            ],
+            Expression::IntegerLiteral { value, .. } => vec![
+                ("kind", Json::string("IntegerLiteral")),
+                ("value", Json::Number(*value as i128)),
+            ],
+            Expression::StringLiteral { value, .. } => vec![
+                ("kind", Json::string("StringLiteral")),
+                ("value", Json::string(value)),
+            ],
+            Expression::CharLiteral { value, .. } => vec![
+                ("kind", Json::string("CharLiteral")),
+                ("value", Json::String(value.to_string())),
+            ],
+            Expression::Boolean { value, .. } => vec![
+                ("kind", Json::string("Boolean")),
+                ("value", Json::Bool(*value)),
+            ],
+            Expression::PrefixExpression {
+                operator, right, ..
+            } => vec![
+                ("kind", Json::string("PrefixExpression")),
+                ("operator", Json::string(operator)),
+                ("right", right.to_json()),
+            ],
+            Expression::InfixExpression {
+                left,
+                operator,
+                right,
+                ..
+            } => vec![
+                ("kind", Json::string("InfixExpression")),
+                ("left", left.to_json()),
+                ("operator", Json::string(operator)),
+                ("right", right.to_json()),
+            ],
+            Expression::AssignExpression {
+                left,
+                operator,
+                right,
+                ..
+            } => vec![
+                ("kind", Json::string("AssignExpression")),
+                ("left", left.to_json()),
+                ("operator", Json::string(operator)),
+                ("right", right.to_json()),
+            ],
+            Expression::IfExpression {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => vec![
+                ("kind", Json::string("IfExpression")),
+                ("condition", condition.to_json()),
+                ("consequence", consequence.to_json()),
+                (
+                    "alternative",
+                    alternative.as_ref().map_or(Json::Null, |alt| alt.to_json()),
+                ),
+            ],
+            Expression::WhileExpression {
+                label,
+                condition,
+                consequence,
+                ..
+            } => vec![
+                ("kind", Json::string("WhileExpression")),
+                ("label", label.as_deref().map_or(Json::Null, Json::string)),
+                ("condition", condition.to_json()),
+                ("consequence", consequence.to_json()),
+            ],
+            Expression::BlockExpression { body, .. } => vec![
+                ("kind", Json::string("BlockExpression")),
+                ("body", body.to_json()),
+            ],
+            Expression::CallExpression {
+                function,
+                arguments,
+                ..
+            } => vec![
+                ("kind", Json::string("CallExpression")),
+                ("function", Json::string(function)),
+                (
+                    "arguments",
+                    Json::Array(arguments.iter().map(|arg| arg.to_json()).collect()),
+                ),
+            ],
+        };
+        fields.push(("span", span_json(self.span())));
+
+        Json::object(fields)
+    }
+
     // fn need_next(&self) -> bool {
     //     match self {
     //         Expression::NeedNext => true,
@@ -156,21 +467,28 @@ impl Expression {
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expression::Identifier { value } => write!(f, "{}", value),
-            Expression::IntegerLiteral { value } => write!(f, "{}", value),
-            // Expression::StringLiteral { value } => write!(f, "\"{}\"", value),
-            Expression::PrefixExpression { operator, right } => {
+            Expression::Identifier { value, .. } => write!(f, "{}", value),
+            Expression::IntegerLiteral { value, .. } => write!(f, "{}", value),
+            Expression::StringLiteral { value, .. } => write!(f, "{:?}", value),
+            Expression::CharLiteral { value, .. } => write!(f, "{:?}", value),
+            Expression::PrefixExpression {
+                operator, right, ..
+            } => {
                 write!(f, "({}{})", operator, right)
             }
             Expression::InfixExpression {
                 left,
                 operator,
                 right,
+                ..
             } => write!(f, "({} {} {})", left, operator, right),
-            Expression::AssignExpression { left, right } => {
-                write!(f, "{} = {}", left, right)
-            }
-            // Expression::Boolean { value } => return write!(f, "{}", value),
+            Expression::AssignExpression {
+                left,
+                operator,
+                right,
+                ..
+            } => write!(f, "{} {} {}", left, operator, right),
+            Expression::Boolean { value, .. } => write!(f, "{}", value),
             // Expression::ArrayLiteral { elements } => {
             //     let mut s = "".to_string();
             //     for (i, p) in elements.iter().enumerate() {
@@ -189,14 +507,21 @@ impl fmt::Display for Expression {
                 condition,
                 consequence,
                 alternative,
+                ..
             } => match alternative {
                 Some(alt) => write!(f, "if ({}) {} else {}", condition, consequence, alt),
                 None => write!(f, "if ({}) {}", condition, consequence),
             },
             Expression::WhileExpression {
+                label,
                 condition,
                 consequence,
-            } => write!(f, "while ({}) {}", condition, consequence),
+                ..
+            } => match label {
+                Some(label) => write!(f, "{}: while ({}) {}", label, condition, consequence),
+                None => write!(f, "while ({}) {}", condition, consequence),
+            },
+            Expression::BlockExpression { body, .. } => write!(f, "{}", body),
             // Expression::FunctionLiteral { parameters, body } => {
             //     let mut s = "".to_string();
             //     for (i, p) in parameters.iter().enumerate() {
@@ -211,6 +536,7 @@ impl fmt::Display for Expression {
             Expression::CallExpression {
                 function,
                 arguments,
+                ..
             } => {
                 let mut s = "".to_string();
                 for (i, a) in arguments.iter().enumerate() {
@@ -221,20 +547,18 @@ impl fmt::Display for Expression {
                     }
                 }
                 write!(f, "{}({})", function, s)
-            }
-            // Expression::HashLiteral { pairs } => {
-            //     let mut s = "{ ".to_string();
-            //     for (i, (key, value)) in pairs.iter().enumerate() {
-            //         if i == 0 {
-            //             s += &format!("{}: {}", key, value);
-            //         } else {
-            //             s += &format!(", {}: {}", key, value);
-            //         }
-            //     }
-            //     s += " }";
-            //     return write!(f, "{}", s);
-            // }
-            Expression::NeedNext => write!(f, ""),
+            } // Expression::HashLiteral { pairs } => {
+              //     let mut s = "{ ".to_string();
+              //     for (i, (key, value)) in pairs.iter().enumerate() {
+              //         if i == 0 {
+              //             s += &format!("{}: {}", key, value);
+              //         } else {
+              //             s += &format!(", {}: {}", key, value);
+              //         }
+              //     }
+              //     s += " }";
+              //     return write!(f, "{}", s);
+              // }
         }
     }
 }
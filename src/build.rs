@@ -0,0 +1,214 @@
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, thread};
+
+use super::diagnostic;
+
+/// A directory that only this process uses; it is deleted with everything
+/// inside it when dropped. Only its owner can access it.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> io::Result<TempDir> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.subsec_nanos());
+            let path = env::temp_dir().join(format!(
+                "rust-build-{}-{}-{}",
+                process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Assembles `asm` and links it into an executable at `output` using the
+/// system C compiler driver (`$CC`, or `cc` by default).
+pub fn link(asm: &str, output: &Path) -> Result<(), Box<diagnostic::Diagnostic>> {
+    let dir = temp_dir()?;
+    cc_in(&dir, asm, &[], output)
+}
+
+/// Assembles `asm` into an object file at `output` without linking it.
+pub fn assemble(asm: &str, output: &Path) -> Result<(), Box<diagnostic::Diagnostic>> {
+    let dir = temp_dir()?;
+    cc_in(&dir, asm, &["-c"], output)
+}
+
+fn temp_dir() -> Result<TempDir, Box<diagnostic::Diagnostic>> {
+    TempDir::new().map_err(|err| {
+        Box::new(diagnostic::Diagnostic::error(
+            "E0301",
+            format!("couldn't create a temporary directory: {}", err),
+        ))
+    })
+}
+
+fn cc_in(
+    dir: &TempDir,
+    asm: &str,
+    flags: &[&str],
+    output: &Path,
+) -> Result<(), Box<diagnostic::Diagnostic>> {
+    let asm_path = dir.path().join("main.s");
+    fs::write(&asm_path, asm).map_err(|err| {
+        Box::new(diagnostic::Diagnostic::error(
+            "E0301",
+            format!("couldn't write `{}`: {}", asm_path.display(), err),
+        ))
+    })?;
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&cc)
+        .args(flags)
+        .arg(&asm_path)
+        .arg("-o")
+        .arg(output)
+        .output()
+        .map_err(|err| {
+            Box::new(
+                diagnostic::Diagnostic::error("E0302", format!("couldn't run `{}`: {}", cc, err))
+                    .with_note("an assembler and linker are required to build executables"),
+            )
+        })?;
+
+    if !result.status.success() {
+        let mut d = diagnostic::Diagnostic::error(
+            "E0303",
+            format!("`{}` failed to assemble or link the program", cc),
+        );
+        for line in String::from_utf8_lossy(&result.stderr).lines() {
+            d = d.with_note(line);
+        }
+        return Err(Box::new(d));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct Limits {
+    /// Wall-clock time after which the program is killed.
+    pub timeout: Option<Duration>,
+    /// Maximum size of the program's address space in bytes.
+    pub memory: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Exited(i32),
+    Signaled(i32),
+    TimedOut,
+}
+
+/// Builds `asm` into a temporary directory and runs it with `args`. The
+/// program inherits stdin, stdout and stderr from this process.
+pub fn run(
+    asm: &str,
+    args: &[String],
+    limits: &Limits,
+) -> Result<Outcome, Box<diagnostic::Diagnostic>> {
+    let dir = temp_dir()?;
+    let exe_path = dir.path().join("main");
+    cc_in(&dir, asm, &[], &exe_path)?;
+
+    let mut command = Command::new(&exe_path);
+    command.args(args);
+    if let Some(bytes) = limits.memory {
+        // SAFETY: `setrlimit` is async-signal-safe and nothing else is done
+        // between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    let mut child = command.spawn().map_err(|err| {
+        Box::new(diagnostic::Diagnostic::error(
+            "E0304",
+            format!("couldn't run the program: {}", err),
+        ))
+    })?;
+
+    let wait_error = |err: io::Error| {
+        Box::new(diagnostic::Diagnostic::error(
+            "E0304",
+            format!("couldn't wait for the program: {}", err),
+        ))
+    };
+    let status = match limits.timeout {
+        None => child.wait().map_err(wait_error)?,
+        Some(timeout) => {
+            let start = Instant::now();
+            loop {
+                if let Some(status) = child.try_wait().map_err(wait_error)? {
+                    break status;
+                }
+                if start.elapsed() >= timeout {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Ok(Outcome::TimedOut);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+
+    match (status.code(), status.signal()) {
+        (Some(code), _) => Ok(Outcome::Exited(code)),
+        (None, Some(signal)) => Ok(Outcome::Signaled(signal)),
+        (None, None) => Ok(Outcome::Exited(1)),
+    }
+}
+
+#[cfg(test)]
+mod build_tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_temp_dir() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -1,70 +1,341 @@
-use crate::{ast::Expression, environment};
+use crate::{ast::Expression, diagnostic, environment, runtime, token};
 
 use super::ast;
-use core::panic;
 use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
 use std::rc::Rc;
-// use std::collections::HashMap;
 
-enum Status {
-    Default,
-    Return,
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    UnknownVariable {
+        name: String,
+        span: token::Span,
+    },
+    UnknownFunction {
+        name: String,
+        span: token::Span,
+    },
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: token::Span,
+    },
+    InvalidAssignmentTarget {
+        span: token::Span,
+    },
+    AssignToUndeclared {
+        name: String,
+        span: token::Span,
+    },
+    AssignToImmutable {
+        name: String,
+        /// The declaration of the variable.
+        declared: token::Span,
+        span: token::Span,
+    },
+    IntegerOutOfRange {
+        span: token::Span,
+    },
+    DuplicateFunction {
+        name: String,
+        /// The earlier definition.
+        first: token::Span,
+        span: token::Span,
+    },
+    MissingMain,
+    MissingReturn {
+        name: String,
+        span: token::Span,
+    },
+    LoopControlOutsideLoop {
+        /// `break` or `continue`.
+        keyword: String,
+        span: token::Span,
+    },
+    UndeclaredLabel {
+        label: String,
+        span: token::Span,
+    },
+    /// An `if` without `else` whose block ends in an expression.
+    MissingElse {
+        span: token::Span,
+    },
+    UnsupportedConstruct {
+        description: String,
+        span: token::Span,
+    },
+}
+
+impl CompileError {
+    /// The location of the error, if it is caused by one piece of the source.
+    pub fn span(&self) -> Option<token::Span> {
+        match self {
+            CompileError::UnknownVariable { span, .. } => Some(*span),
+            CompileError::UnknownFunction { span, .. } => Some(*span),
+            CompileError::ArityMismatch { span, .. } => Some(*span),
+            CompileError::InvalidAssignmentTarget { span } => Some(*span),
+            CompileError::AssignToUndeclared { span, .. } => Some(*span),
+            CompileError::AssignToImmutable { span, .. } => Some(*span),
+            CompileError::IntegerOutOfRange { span } => Some(*span),
+            CompileError::DuplicateFunction { span, .. } => Some(*span),
+            CompileError::MissingMain => None,
+            CompileError::MissingReturn { span, .. } => Some(*span),
+            CompileError::LoopControlOutsideLoop { span, .. } => Some(*span),
+            CompileError::UndeclaredLabel { span, .. } => Some(*span),
+            CompileError::MissingElse { span } => Some(*span),
+            CompileError::UnsupportedConstruct { span, .. } => Some(*span),
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> diagnostic::Diagnostic {
+        let (code, label) = match self {
+            CompileError::UnknownVariable { .. } => ("E0201", "not found in this scope"),
+            CompileError::UnknownFunction { .. } => ("E0202", "not found in this program"),
+            CompileError::ArityMismatch { .. } => ("E0203", "wrong number of arguments"),
+            CompileError::InvalidAssignmentTarget { .. } => ("E0204", "cannot be assigned to"),
+            CompileError::UnsupportedConstruct { .. } => ("E0205", "not supported"),
+            CompileError::IntegerOutOfRange { .. } => {
+                ("E0206", "does not fit in a signed 64-bit integer")
+            }
+            CompileError::AssignToUndeclared { .. } => ("E0207", "not declared in this scope"),
+            CompileError::AssignToImmutable { .. } => ("E0208", "cannot assign twice"),
+            CompileError::DuplicateFunction { .. } => ("E0209", "redefined here"),
+            CompileError::MissingMain => ("E0210", ""),
+            CompileError::MissingReturn { .. } => ("E0211", "can reach its end without a value"),
+            CompileError::LoopControlOutsideLoop { .. } => ("E0212", "not inside a loop"),
+            CompileError::UndeclaredLabel { .. } => ("E0213", "undeclared label"),
+            CompileError::MissingElse { .. } => ("E0214", "has a value but no `else`"),
+        };
+
+        let mut d = diagnostic::Diagnostic::error(code, self.to_string());
+        if let Some(span) = self.span() {
+            d = d.with_primary(span, label);
+        }
+        match self {
+            CompileError::AssignToUndeclared { name, .. } => {
+                d.with_note(&format!("declare it first with `let mut {} = ...;`", name))
+            }
+            CompileError::AssignToImmutable { name, declared, .. } => d
+                .with_secondary(*declared, "declared immutable here")
+                .with_note(&format!(
+                    "use `let mut {}` to allow assigning to it; parameters cannot be assigned to",
+                    name
+                )),
+            CompileError::DuplicateFunction { first, .. } => {
+                d.with_secondary(*first, "first defined here")
+            }
+            CompileError::MissingMain => {
+                d.with_note("every program needs a `fn main()` where execution starts")
+            }
+            CompileError::MissingElse { .. } => d.with_note(
+                "an `if` without `else` is always 0, so its block cannot end in an expression; add an `else` or end the block with `;`",
+            ),
+            CompileError::MissingReturn { .. } => d.with_note(
+                "end every path with `return` or with a final expression that has no `;` after it",
+            ),
+            _ => d,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UnknownVariable { name, .. } => {
+                write!(f, "cannot find variable `{}`", name)
+            }
+            CompileError::UnknownFunction { name, .. } => {
+                write!(f, "cannot find function `{}`", name)
+            }
+            CompileError::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "function `{}` takes {} argument{} but {} {} supplied",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            CompileError::InvalidAssignmentTarget { .. } => {
+                write!(f, "invalid left-hand side of assignment")
+            }
+            CompileError::AssignToUndeclared { name, .. } => {
+                write!(f, "cannot assign to undeclared variable `{}`", name)
+            }
+            CompileError::DuplicateFunction { name, .. } => {
+                write!(f, "function `{}` is defined more than once", name)
+            }
+            CompileError::MissingMain => write!(f, "no `main` function in the program"),
+            CompileError::MissingReturn { name, .. } => write!(
+                f,
+                "function `{}` returns a value on some paths but not all",
+                name
+            ),
+            CompileError::LoopControlOutsideLoop { keyword, .. } => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            CompileError::UndeclaredLabel { label, .. } => {
+                write!(f, "use of undeclared label `{}`", label)
+            }
+            CompileError::MissingElse { .. } => write!(f, "`if` may be missing an `else` clause"),
+            CompileError::AssignToImmutable { name, .. } => {
+                write!(f, "cannot assign twice to immutable variable `{}`", name)
+            }
+            CompileError::UnsupportedConstruct { description, .. } => {
+                write!(f, "unsupported: {}", description)
+            }
+            CompileError::IntegerOutOfRange { .. } => {
+                write!(f, "integer literal is out of range")
+            }
+        }
+    }
+}
+
+/// A loop that encloses the code being compiled.
+struct Loop {
+    label: Option<String>,
+    /// The number in the loop's `.Lbegin` and `.Lend` labels.
+    id: usize,
+    /// Offset of the frame slot holding `rsp` as it was when the loop was
+    /// entered. `break` and `continue` restore it, which drops whatever the
+    /// body had pushed when they ran.
+    rsp_offset: usize,
 }
 
 pub struct Compiler {
     env: Rc<RefCell<environment::Environment>>,
-    status: Status,
+    /// Names of the functions the program defines.
+    functions: HashSet<String>,
+    called: HashSet<String>,
+    /// String literals, stored in `.rodata` and labelled by their index.
+    strings: Vec<String>,
+    /// Number of local labels handed out so far. Labels are unique across the
+    /// whole program, not just within one scope.
+    label_count: usize,
+    /// Bytes of stack needed by the locals of the function being compiled.
+    frame_size: usize,
+    /// The loops around the code being compiled, innermost last.
+    loops: Vec<Loop>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Compiler {
-            env: Rc::new(RefCell::new(environment::Environment::new(0, 0, 0))),
-            status: Status::Default,
+            env: Rc::new(RefCell::new(environment::Environment::new(0, 0))),
+            functions: HashSet::new(),
+            called: HashSet::new(),
+            strings: Vec::new(),
+            label_count: 0,
+            frame_size: 0,
+            loops: Vec::new(),
         }
     }
 
-    pub fn compile_program(&mut self, program: ast::Program) -> Option<String> {
+    /// Compiles a program that has passed the resolver, which reports unknown
+    /// names and calls with the wrong number of arguments.
+    pub fn compile_program(&mut self, program: &ast::Program) -> Result<String, Vec<CompileError>> {
         let mut asm = String::new();
         asm += ".intel_syntax noprefix\n";
         asm += ".globl main\n";
 
-        for func in program.functions {
-            if let Some(r) = self.compile_function(func) {
-                asm += &r;
+        self.functions = program
+            .functions
+            .iter()
+            .map(|func| func.name.clone())
+            .collect();
+
+        let mut errors = Vec::new();
+        for func in &program.functions {
+            let env = Rc::clone(&self.env);
+            match self.compile_function(func) {
+                Ok(r) => asm += &r,
+                Err(err) => {
+                    errors.push(err);
+                    self.env = env;
+                    self.loops.clear();
+                }
+            }
+        }
+
+        // Builtins are only emitted if they are called and not redefined.
+        let mut called: Vec<&String> = self.called.iter().collect();
+        called.sort();
+        for name in called {
+            if self.functions.contains(name) {
+                continue;
+            }
+            if let Some(builtin) = runtime::lookup(name) {
+                asm += builtin.asm;
+            }
+        }
+
+        if !self.strings.is_empty() {
+            asm += ".section .rodata\n";
+            for (i, value) in self.strings.iter().enumerate() {
+                asm += &format!(".Lstr{}:\n", i);
+                asm += &format!("  .string \"{}\"\n", escape_string(value));
             }
         }
 
-        Some(asm)
+        if errors.is_empty() {
+            Ok(asm)
+        } else {
+            Err(errors)
+        }
     }
 
-    pub fn compile_function(&mut self, function: ast::Function) -> Option<String> {
+    pub fn compile_function(&mut self, function: &ast::Function) -> Result<String, CompileError> {
         let mut asm = String::new();
-        asm += &format!("{}:\n", function.name);
+        asm += &format!("{}:\n", symbol(&function.name));
 
         asm += "  push rbp\n";
         asm += "  mov rbp, rsp\n";
 
+        if function.parameters.len() > environment::REGISTERS.len() {
+            return Err(CompileError::UnsupportedConstruct {
+                description: format!(
+                    "functions with more than {} parameters",
+                    environment::REGISTERS.len()
+                ),
+                span: function.span,
+            });
+        }
+
         self.env = Rc::new(RefCell::new(environment::Environment::new_fn_env(
             Rc::clone(&self.env),
         )));
 
-        for (i, param) in function.parameters.into_iter().rev().enumerate() {
-            if i >= environment::REGISTERS.len() {
-                return None;
-            }
-
-            self.env.borrow_mut().set(&param);
+        for (i, param) in function.parameters.iter().rev().enumerate() {
+            self.env.borrow_mut().set(param, false, function.span);
             asm += &format!("  sub rsp, {}\n", 8);
             asm += &format!("  mov [rsp], {}\n", environment::REGISTERS[i]);
         }
 
-        if let Some(r) = self.compile_statement(function.body) {
-            asm += &r;
-            if let Status::Return = self.status {
-                self.status = Status::Default;
-            }
+        // Every local gets its slot up front, so that no slot is ever placed
+        // among the temporaries of an expression that is being evaluated.
+        self.frame_size = self.env.borrow().offset;
+        let body = self.compile_statement(&function.body)?;
+        let locals = self.frame_size - self.env.borrow().offset;
+        if locals > 0 {
+            asm += &format!("  sub rsp, {}\n", locals);
         }
+        asm += &body;
+        // Running off the end returns the value of the body, which is its tail
+        // expression or 0.
+        asm += "  pop rax\n";
 
         let outer = Rc::clone(self.env.borrow_mut().outer.as_ref().unwrap());
         self.env = outer;
@@ -73,133 +344,187 @@ impl Compiler {
         asm += "  pop rbp\n";
         asm += "  ret\n";
 
-        Some(asm)
+        Ok(asm)
     }
 
-    fn compile_statement(&mut self, stmt: ast::Statement) -> Option<String> {
+    fn compile_statement(&mut self, stmt: &ast::Statement) -> Result<String, CompileError> {
         match stmt {
-            // ast::Statement::Let { name, value } => match self.eval_expression(value) {
-            //     Some(val) => {
-            //         if !Evaluator::is_error(&val) {
-            //             self.env.borrow_mut().set(name.to_string(), Rc::clone(&val));
-            //         }
-            //         return Some(Rc::clone(&val));
-            //     }
-            //     None => return None,
-            // },
-            ast::Statement::Return { return_value } => {
-                match self.compile_expression(return_value) {
-                    Some(value) => {
-                        self.status = Status::Return;
-                        let mut asm = value;
-                        asm += "  pop rax\n";
-                        asm += "  mov rsp, rbp\n";
-                        asm += "  pop rbp\n";
-                        asm += "  ret\n";
-                        Some(asm)
-                    }
-                    None => None,
-                }
+            ast::Statement::Let {
+                name,
+                mutable,
+                value,
+                span,
+            } => {
+                // The initializer is compiled before the new variable exists,
+                // so `let x = x + 1;` reads the variable it shadows.
+                let mut asm = self.compile_expression(value)?;
+
+                self.env.borrow_mut().set(name, *mutable, *span);
+                let offset = self.env.borrow().offset;
+                self.frame_size = self.frame_size.max(offset);
+
+                asm += &format!("# let {}\n", name);
+                asm += "  pop rdi\n";
+                asm += "  mov rax, rbp\n";
+                asm += &format!("  sub rax, {}\n", offset);
+                asm += "  mov [rax], rdi\n";
+                asm += "  push rdi\n";
+                Ok(asm)
+            }
+            ast::Statement::Return { return_value, .. } => {
+                let mut asm = self.compile_expression(return_value)?;
+                asm += "  pop rax\n";
+                asm += "  mov rsp, rbp\n";
+                asm += "  pop rbp\n";
+                asm += "  ret\n";
+                Ok(asm)
+            }
+            ast::Statement::Break { label, span } => {
+                self.compile_loop_control("break", label.as_deref(), *span)
+            }
+            ast::Statement::Continue { label, span } => {
+                self.compile_loop_control("continue", label.as_deref(), *span)
+            }
+            ast::Statement::Expression { expression, .. } => self.compile_expression(expression),
+            ast::Statement::Block { statements, .. } => {
+                self.compile_block_statement(statements, stmt.tail().is_some())
             }
-            ast::Statement::Expression { expression } => self.compile_expression(expression),
-            ast::Statement::Block { statements } => self.compile_block_statement(statements),
         }
     }
 
-    fn compile_block_statement(&mut self, statements: Vec<ast::Statement>) -> Option<String> {
+    /// Compiles a block, which like every statement pushes one value: that of
+    /// its last statement if `has_tail`, and 0 otherwise.
+    fn compile_block_statement(
+        &mut self,
+        statements: &[ast::Statement],
+        has_tail: bool,
+    ) -> Result<String, CompileError> {
         let mut asm = String::new();
         self.env = Rc::new(RefCell::new(environment::Environment::new_block_env(
             Rc::clone(&self.env),
         )));
 
-        for stmt in statements {
-            if let Some(result) = self.compile_statement(stmt) {
-                asm += &result;
-                asm += "  pop rax\n";
+        for (i, stmt) in statements.iter().enumerate() {
+            asm += &self.compile_statement(stmt)?;
+            // Nothing after a `return` in the block itself can run. One nested
+            // in an expression may be skipped, so the rest is still compiled.
+            if let ast::Statement::Return { .. } = stmt {
+                break;
+            }
 
-                if let Status::Return = self.status {
-                    return Some(asm);
-                }
+            if has_tail && i == statements.len() - 1 {
+                break;
             }
+            asm += "  pop rax\n";
+        }
+        if !has_tail {
+            asm += "  push 0\n";
         }
 
         let outer = Rc::clone(self.env.borrow_mut().outer.as_ref().unwrap());
         self.env = outer;
 
-        Some(asm)
+        Ok(asm)
     }
 
-    fn compile_expression(&mut self, exp: ast::Expression) -> Option<String> {
-        // let mut asm = String::new();
-
+    fn compile_expression(&mut self, exp: &ast::Expression) -> Result<String, CompileError> {
         match exp {
-            ast::Expression::Identifier { value } => self.compile_identifier(value),
-            ast::Expression::IntegerLiteral { value } => Some(format!("  push {}\n", value)),
-            // ast::Expression::StringLiteral { value } => {
-            //     Some(Rc::new(object::Object::String(value)))
-            // }
-            ast::Expression::PrefixExpression { operator, right } => {
-                match self.compile_expression(*right) {
-                    Some(right_evaluated) => {
-                        self.compile_prefix_expression(operator, right_evaluated)
+            ast::Expression::Identifier { value, .. } => Ok(self.compile_identifier(value)),
+            ast::Expression::IntegerLiteral { value, span } => match i64::try_from(*value) {
+                Ok(value) => Ok(Self::compile_integer(value)),
+                Err(_) => Err(CompileError::IntegerOutOfRange { span: *span }),
+            },
+            ast::Expression::CharLiteral { value, .. } => {
+                Ok(Self::compile_integer(u32::from(*value).into()))
+            }
+            ast::Expression::StringLiteral { value, .. } => {
+                let index = match self.strings.iter().position(|s| s == value) {
+                    Some(index) => index,
+                    None => {
+                        self.strings.push(value.clone());
+                        self.strings.len() - 1
                     }
-                    None => None,
-                }
+                };
+
+                let mut asm = String::new();
+                asm += &format!("  lea rax, [rip + .Lstr{}]\n", index);
+                asm += "  push rax\n";
+                Ok(asm)
+            }
+            ast::Expression::PrefixExpression {
+                operator,
+                right,
+                span,
+            } => self.compile_prefix_expression(operator, right, *span),
+            ast::Expression::InfixExpression {
+                left,
+                operator,
+                right,
+                ..
+            } if operator == "&&" || operator == "||" => {
+                self.compile_logical_expression(operator, left, right)
             }
             ast::Expression::InfixExpression {
                 left,
                 operator,
                 right,
-            } => match self.compile_expression(*right) {
-                Some(right_evaluated) => {
-                    // if Compiler::is_error(&right_evaluated) {
-                    //     return Some(right_evaluated);
-                    // }
-                    match self.compile_expression(*left) {
-                        Some(left_evaluated) => {
-                            // if Compiler::is_error(&left_evaluated) {
-                            //     return Some(left_evaluated);
-                            // }
-                            self.compile_infix_expression(operator, left_evaluated, right_evaluated)
-                        }
-                        None => None,
+                span,
+            } => {
+                let right_evaluated = self.compile_expression(right)?;
+                let left_evaluated = self.compile_expression(left)?;
+                self.compile_infix_expression(operator, left_evaluated, right_evaluated, *span)
+            }
+            ast::Expression::AssignExpression {
+                left,
+                operator,
+                right,
+                span,
+            } => {
+                let mut asm = String::new();
+
+                let value = match left.as_ref() {
+                    ast::Expression::Identifier { value, .. } => value,
+                    left => {
+                        return Err(CompileError::InvalidAssignmentTarget { span: left.span() })
                     }
+                };
+                let variable = self
+                    .env
+                    .borrow()
+                    .get(value)
+                    .unwrap_or_else(|| panic!("unresolved variable `{}`", value));
+                if !variable.mutable {
+                    return Err(CompileError::AssignToImmutable {
+                        name: value.clone(),
+                        declared: variable.span,
+                        span: *span,
+                    });
                 }
-                None => None,
-            },
-            ast::Expression::AssignExpression { left, right } => {
-                let mut asm = String::new();
+                let right_evaluated = self.compile_expression(right)?;
+
+                asm += &format!("# {}\n", value);
+                asm += "  mov rax, rbp\n";
+                asm += &format!("  sub rax, {}\n", variable.offset);
+                asm += "  push rax\n";
+
+                asm += &right_evaluated;
 
-                match self.compile_expression(*right) {
-                    Some(right_evaluated) => match *left {
-                        ast::Expression::Identifier { value } => {
-                            asm += &format!("# {}\n", value);
-                            if !self.env.borrow().contains_key(&value) {
-                                self.env.borrow_mut().set(&value);
-                                asm += &format!("  sub rsp, {}\n", 8);
-                            }
-
-                            if let Some(variable) = self.env.borrow().get(&value) {
-                                asm += "  mov rax, rbp\n";
-                                asm += &format!("  sub rax, {}\n", variable.offset);
-                                asm += "  push rax\n";
-
-                                asm += &right_evaluated;
-
-                                asm += "  pop rdi\n";
-                                asm += "  pop rax\n";
-                                asm += "  mov [rax], rdi\n";
-                                asm += "  push rdi\n";
-                            }
-
-                            Some(asm)
-                        }
-                        _ => None,
-                    },
-                    None => None,
+                asm += "  pop rdi\n";
+                if let Some(compound) = operator.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    // The address stays on the stack while the new value is
+                    // computed from the old one.
+                    asm += "  mov rax, [rsp]\n";
+                    asm += "  mov rax, [rax]\n";
+                    asm += &Self::compile_binary_operation(compound, *span)?;
+                    asm += "  mov rdi, rax\n";
                 }
+                asm += "  pop rax\n";
+                asm += "  mov [rax], rdi\n";
+                asm += "  push rdi\n";
+
+                Ok(asm)
             }
-            // ast::Expression::Boolean { value } => return Some(Evaluator::eval_boolean(value)),
+            ast::Expression::Boolean { value, .. } => Ok(Self::compile_integer(*value as i64)),
             // ast::Expression::ArrayLiteral { elements } => {
             //     let elms = self.eval_expressions(elements);
             //     if elms.len() == 1 && Evaluator::is_error(&elms[0]) {
@@ -224,11 +549,15 @@ impl Compiler {
                 condition,
                 consequence,
                 alternative,
-            } => self.compile_if_expression(*condition, *consequence, alternative),
+                ..
+            } => self.compile_if_expression(condition, consequence, alternative.as_deref()),
             ast::Expression::WhileExpression {
+                label,
                 condition,
                 consequence,
-            } => self.compile_while_expression(*condition, *consequence),
+                ..
+            } => self.compile_while_expression(label.as_deref(), condition, consequence),
+            ast::Expression::BlockExpression { body, .. } => self.compile_statement(body),
             // ast::Expression::FunctionLiteral { parameters, body } => {
             //     return Some(Rc::new(object::Object::Function {
             //         parameters,
@@ -239,50 +568,46 @@ impl Compiler {
             ast::Expression::CallExpression {
                 function,
                 arguments,
+                ..
             } => {
-                // if Evaluator::is_error(&func) {
-                //     return Some(func);
-                // }
+                self.called.insert(function.clone());
 
+                // Evaluate every argument before filling any register, since
+                // computing one argument may clobber the registers (a call, a
+                // shift or a division all do).
                 let mut asm = String::new();
-                for (i, arg) in arguments.into_iter().rev().enumerate() {
-                    if let Some(result) = self.compile_expression(arg) {
-                        if i >= environment::REGISTERS.len() {
-                            return None;
-                        }
-                        asm += &result;
-                        asm += "  pop rax\n";
-                        asm += &format!("  mov {}, rax\n", environment::REGISTERS[i]);
-                    }
+                for arg in arguments {
+                    asm += &self.compile_expression(arg)?;
+                }
+                for register in &environment::REGISTERS[..arguments.len()] {
+                    asm += &format!("  pop {}\n", register);
                 }
 
-                asm += &format!("  call {}\n", function);
+                asm += &format!("  call {}\n", symbol(function));
                 asm += "  push rax\n";
 
-                Some(asm)
-            }
-            // ast::Expression::HashLiteral { pairs } => {
-            //     let mut hash = HashMap::new();
-
-            //     for (key_expr, value_expr) in pairs {
-            //         if let Some(key) = self.eval_expression(key_expr) {
-            //             if Self::is_error(&key) {
-            //                 return Some(key);
-            //             }
-            //             if let Some(value) = self.eval_expression(value_expr) {
-            //                 if Evaluator::is_error(&value) {
-            //                     return Some(value);
-            //                 }
-            //                 hash.insert(key, value);
-            //             }
-            //         } else {
-            //             return None;
-            //         }
-            //     }
-
-            //     Some(Rc::new(object::Object::Hash(hash)))
-            // }
-            ast::Expression::NeedNext => None,
+                Ok(asm)
+            } // ast::Expression::HashLiteral { pairs } => {
+              //     let mut hash = HashMap::new();
+
+              //     for (key_expr, value_expr) in pairs {
+              //         if let Some(key) = self.eval_expression(key_expr) {
+              //             if Self::is_error(&key) {
+              //                 return Some(key);
+              //             }
+              //             if let Some(value) = self.eval_expression(value_expr) {
+              //                 if Evaluator::is_error(&value) {
+              //                     return Some(value);
+              //                 }
+              //                 hash.insert(key, value);
+              //             }
+              //         } else {
+              //             return None;
+              //         }
+              //     }
+
+              //     Some(Rc::new(object::Object::Hash(hash)))
+              // }
         }
     }
 
@@ -374,27 +699,100 @@ impl Compiler {
     //     return result;
     // }
 
-    fn compile_prefix_expression(&mut self, operator: String, right: String) -> Option<String> {
-        match &*operator {
-            // "!" => return Evaluator::eval_bang_operator_expression(right),
+    /// Pushes `value`, which `push` can only encode directly if it fits in
+    /// 32 bits.
+    fn compile_integer(value: i64) -> String {
+        if i32::try_from(value).is_ok() {
+            format!("  push {}\n", value)
+        } else {
+            format!("  mov rax, {}\n  push rax\n", value)
+        }
+    }
+
+    fn compile_prefix_expression(
+        &mut self,
+        operator: &str,
+        right: &ast::Expression,
+        span: token::Span,
+    ) -> Result<String, CompileError> {
+        match operator {
+            // Any nonzero value is true, and `!` turns it into 0 or 1 like the
+            // comparison operators do.
+            "!" => {
+                let mut asm = self.compile_expression(right)?;
+                asm += "  pop rax\n";
+                asm += "  cmp rax, 0\n";
+                asm += "  sete al\n";
+                asm += "  movzb rax, al\n";
+                asm += "  push rax\n";
+                Ok(asm)
+            }
+            "~" => {
+                let mut asm = self.compile_expression(right)?;
+                asm += "  pop rax\n";
+                asm += "  not rax\n";
+                asm += "  push rax\n";
+                Ok(asm)
+            }
             "-" => {
-                if let Some(left) = self.compile_expression(Expression::IntegerLiteral { value: 0 })
-                {
-                    self.compile_infix_expression(operator, left, right)
-                } else {
-                    None
+                // Negated literals are folded, which also covers `i64::MIN`,
+                // whose magnitude does not fit in an `i64` by itself.
+                if let ast::Expression::IntegerLiteral { value, .. } = right {
+                    if *value <= i64::MIN.unsigned_abs() {
+                        return Ok(Self::compile_integer(0i64.wrapping_sub_unsigned(*value)));
+                    }
                 }
+
+                let right = self.compile_expression(right)?;
+                let left =
+                    self.compile_expression(&Expression::IntegerLiteral { value: 0, span })?;
+                self.compile_infix_expression(operator, left, right, span)
             }
-            _ => None,
+            _ => Err(CompileError::UnsupportedConstruct {
+                description: format!("prefix operator `{}`", operator),
+                span,
+            }),
+        }
+    }
+
+    /// Compiles `&&` and `||`, which only evaluate `right` when `left` does
+    /// not already decide the result.
+    fn compile_logical_expression(
+        &mut self,
+        operator: &str,
+        left: &ast::Expression,
+        right: &ast::Expression,
+    ) -> Result<String, CompileError> {
+        let mut asm = String::new();
+        let label_count = self.new_label();
+        let (jump, short_circuit) = match operator {
+            "&&" => ("je", 0),
+            _ => ("jne", 1),
+        };
+
+        for operand in [left, right] {
+            asm += &self.compile_expression(operand)?;
+            asm += "  pop rax\n";
+            asm += "  cmp rax, 0\n";
+            asm += &format!("  {} .Lshort{}\n", jump, label_count);
         }
+
+        asm += &format!("  push {}\n", 1 - short_circuit);
+        asm += &format!("  jmp .Lend{}\n", label_count);
+        asm += &format!(".Lshort{}:\n", label_count);
+        asm += &format!("  push {}\n", short_circuit);
+        asm += &format!(".Lend{}:\n", label_count);
+
+        Ok(asm)
     }
 
     fn compile_infix_expression(
         &mut self,
-        operator: String,
+        operator: &str,
         left: String,
         right: String,
-    ) -> Option<String> {
+        span: token::Span,
+    ) -> Result<String, CompileError> {
         let mut asm = String::new();
 
         asm += &left;
@@ -402,8 +800,17 @@ impl Compiler {
 
         asm += "  pop rdi\n";
         asm += "  pop rax\n";
+        asm += &Self::compile_binary_operation(operator, span)?;
+        asm += "  push rax\n";
+
+        Ok(asm)
+    }
+
+    /// Applies `operator` to `rax` and `rdi`, leaving the result in `rax`.
+    fn compile_binary_operation(operator: &str, span: token::Span) -> Result<String, CompileError> {
+        let mut asm = String::new();
 
-        match &*operator {
+        match operator {
             "+" => asm += "  add rax, rdi\n",
             "-" => asm += "  sub rax, rdi\n",
             "*" => asm += "  imul rax, rdi\n",
@@ -411,6 +818,22 @@ impl Compiler {
                 asm += "  cqo\n";
                 asm += "  idiv rdi\n";
             }
+            "%" => {
+                asm += "  cqo\n";
+                asm += "  idiv rdi\n";
+                asm += "  mov rax, rdx\n";
+            }
+            "&" => asm += "  and rax, rdi\n",
+            "|" => asm += "  or rax, rdi\n",
+            "^" => asm += "  xor rax, rdi\n",
+            "<<" => {
+                asm += "  mov rcx, rdi\n";
+                asm += "  sal rax, cl\n";
+            }
+            ">>" => {
+                asm += "  mov rcx, rdi\n";
+                asm += "  sar rax, cl\n";
+            }
             "==" => {
                 asm += "  cmp rax, rdi\n";
                 asm += "  sete al\n";
@@ -441,12 +864,15 @@ impl Compiler {
                 asm += "  setle al\n";
                 asm += "  movzb rax, al\n";
             }
-            _ => {}
+            _ => {
+                return Err(CompileError::UnsupportedConstruct {
+                    description: format!("infix operator `{}`", operator),
+                    span,
+                })
+            }
         }
 
-        asm += "  push rax\n";
-
-        Some(asm)
+        Ok(asm)
 
         // let err =
         //     object::Object::new_error(format!("type mismatch: {} {} {}", &left, operator, &right));
@@ -614,103 +1040,141 @@ impl Compiler {
 
     fn compile_if_expression(
         &mut self,
-        condition: ast::Expression,
-        consequence: ast::Statement,
-        alternative: Option<Box<ast::Statement>>,
-    ) -> Option<String> {
+        condition: &ast::Expression,
+        consequence: &ast::Statement,
+        alternative: Option<&ast::Statement>,
+    ) -> Result<String, CompileError> {
         let mut asm = String::new();
 
-        if let Some(result) = self.compile_expression(condition) {
-            asm += &result;
-            asm += "  pop rax\n";
-            asm += "  cmp rax, 0\n";
-
-            let label_count = self.env.borrow_mut().inc_label_count() - 1;
-
-            if let Some(alternative) = alternative {
-                asm += &format!("  je .Lelse{}\n", label_count);
-                if let Some(result) = self.compile_statement(consequence) {
-                    if let Status::Return = self.status {
-                        self.status = Status::Default;
-                    }
-                    asm += &result;
-                    asm += "  push rax\n";
-                }
+        asm += &self.compile_expression(condition)?;
+        asm += "  pop rax\n";
+        asm += "  cmp rax, 0\n";
 
-                asm += &format!("  jmp .Lend{}\n", label_count);
-                asm += &format!(".Lelse{}:\n", label_count);
-                asm += "  push rax\n";
+        let label_count = self.new_label();
 
-                if let Some(result) = self.compile_statement(*alternative) {
-                    if let Status::Return = self.status {
-                        self.status = Status::Default;
-                    }
-                    asm += &result;
-                    asm += "  push rax\n";
-                }
-            } else {
-                asm += &format!("  je .Lend{}\n", label_count);
+        // Each branch pushes exactly one value, which becomes the value of the
+        // `if`. Without an `else` the value is 0 either way, since the
+        // resolver rejects a consequence with a tail expression.
+        asm += &format!("  je .Lelse{}\n", label_count);
+        asm += &self.compile_statement(consequence)?;
+        asm += &format!("  jmp .Lend{}\n", label_count);
 
-                if let Some(result) = self.compile_statement(consequence) {
-                    if let Status::Return = self.status {
-                        self.status = Status::Default;
-                    }
-                    asm += &result;
-                    asm += "  push rax\n";
-                }
+        asm += &format!(".Lelse{}:\n", label_count);
+        match alternative {
+            Some(alternative) => {
+                asm += &self.compile_statement(alternative)?;
             }
+            None => asm += "  push 0\n",
+        }
 
-            asm += &format!(".Lend{}:\n", label_count);
-            asm += "  push rax\n";
+        asm += &format!(".Lend{}:\n", label_count);
 
-            Some(asm)
-        } else {
-            None
-        }
+        Ok(asm)
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count - 1
     }
 
     fn compile_while_expression(
         &mut self,
-        condition: ast::Expression,
-        consequence: ast::Statement,
-    ) -> Option<String> {
+        label: Option<&str>,
+        condition: &ast::Expression,
+        consequence: &ast::Statement,
+    ) -> Result<String, CompileError> {
         let mut asm = String::new();
 
-        let label_count = self.env.borrow_mut().inc_label_count() - 1;
+        let label_count = self.new_label();
+        let rsp_offset = self.env.borrow_mut().reserve();
+        self.frame_size = self.frame_size.max(rsp_offset);
+        asm += &format!("  mov [rbp - {}], rsp\n", rsp_offset);
         asm += &format!(".Lbegin{}:\n", label_count);
 
-        if let Some(result) = self.compile_expression(condition) {
-            asm += &result;
-            asm += "  pop rax\n";
-            asm += "  cmp rax, 0\n";
-            asm += &format!("  je .Lend{}\n", label_count);
+        asm += &self.compile_expression(condition)?;
+        asm += "  pop rax\n";
+        asm += "  cmp rax, 0\n";
+        asm += &format!("  je .Lend{}\n", label_count);
+
+        self.loops.push(Loop {
+            label: label.map(String::from),
+            id: label_count,
+            rsp_offset,
+        });
+        let body = self.compile_statement(consequence);
+        self.loops.pop();
+        asm += &body?;
+        asm += "  pop rax\n";
 
-            if let Some(result) = self.compile_statement(consequence) {
-                asm += &result;
-                asm += "  push rax\n";
+        asm += &format!("  jmp .Lbegin{}\n", label_count);
+        asm += &format!(".Lend{}:\n", label_count);
+        // A loop has no value of its own.
+        asm += "  push 0\n";
+
+        Ok(asm)
+    }
+
+    /// Compiles `break` or `continue`, which jump to the end or the start of
+    /// the loop they refer to. Control never falls through, so unlike other
+    /// statements they push no value.
+    fn compile_loop_control(
+        &mut self,
+        keyword: &str,
+        label: Option<&str>,
+        span: token::Span,
+    ) -> Result<String, CompileError> {
+        let target = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|l| l.label.as_deref() == Some(label)),
+            None => self.loops.last(),
+        };
+        let target = match (target, label) {
+            (Some(target), _) => target,
+            (None, Some(label)) if !self.loops.is_empty() => {
+                return Err(CompileError::UndeclaredLabel {
+                    label: label.to_string(),
+                    span,
+                })
+            }
+            (None, _) => {
+                return Err(CompileError::LoopControlOutsideLoop {
+                    keyword: keyword.to_string(),
+                    span,
+                })
             }
+        };
 
-            asm += &format!("  jmp .Lbegin{}\n", label_count);
-            asm += &format!(".Lend{}:\n", label_count);
+        let mut asm = format!("# {}\n", keyword);
+        asm += &format!("  mov rsp, [rbp - {}]\n", target.rsp_offset);
+        if keyword == "break" {
+            asm += &format!("  jmp .Lend{}\n", target.id);
+        } else {
+            asm += &format!("  jmp .Lbegin{}\n", target.id);
         }
 
-        Some(asm)
+        Ok(asm)
     }
 
-    fn compile_identifier(&mut self, ident: String) -> Option<String> {
-        if let Some(variable) = self.env.borrow().get(&ident) {
-            let mut asm = String::new();
-            asm += &format!("# ident: {}, offset: {}\n", ident, variable.offset);
-            asm += "  mov rax, rbp\n";
-            asm += &format!("  sub rax, {}\n", variable.offset);
-            asm += "  mov rax, [rax]\n";
-            asm += "  push rax\n";
-            return Some(asm);
-        }
+    fn compile_identifier(&mut self, ident: &str) -> String {
         // if let Some(value) = self.builtin.get(&ident) {
         //     return Some(Rc::clone(value));
         // }
-        panic!("identifier not found: {}", ident);
+        let variable = self
+            .env
+            .borrow()
+            .get(ident)
+            .unwrap_or_else(|| panic!("unresolved variable `{}`", ident));
+
+        let mut asm = String::new();
+        asm += &format!("# ident: {}, offset: {}\n", ident, variable.offset);
+        asm += "  mov rax, rbp\n";
+        asm += &format!("  sub rax, {}\n", variable.offset);
+        asm += "  mov rax, [rax]\n";
+        asm += "  push rax\n";
+        asm
     }
 
     // fn is_truthy(obj: Rc<object::Object>) -> bool {
@@ -728,6 +1192,131 @@ impl Compiler {
     // }
 }
 
+/// The assembly symbol of the function `name`. Every function but `main`,
+/// the entry point, gets a `_` prefix, so that no function can be mistaken
+/// for a register such as `r8` or an assembler keyword such as `offset`.
+fn symbol(name: &str) -> String {
+    if name == "main" {
+        name.to_string()
+    } else {
+        format!("_{}", name)
+    }
+}
+
+/// Escapes `s` for a `.string` directive, which adds the terminating NUL.
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::new();
+    for b in s.bytes() {
+        match b {
+            b'"' => escaped += "\\\"",
+            b'\\' => escaped += "\\\\",
+            b' '..=b'~' => escaped.push(b as char),
+            _ => escaped += &format!("\\{:03o}", b),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::super::{lexer, parser};
+    use super::*;
+
+    fn compile(input: &str) -> Result<String, Vec<CompileError>> {
+        let l = lexer::Lexer::new(input);
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+
+        Compiler::new().compile_program(&program)
+    }
+
+    fn compile_error(input: &str) -> CompileError {
+        let mut errors = compile(input).expect_err("program compiled");
+        assert_eq!(errors.len(), 1);
+        errors.remove(0)
+    }
+
+    #[test]
+    fn test_string_section() {
+        let asm = compile(r#"fn main() { print("a"); print("b\n"); print("a"); }"#).unwrap();
+
+        assert_eq!(asm.matches("lea rax, [rip + .Lstr0]").count(), 2);
+        assert_eq!(asm.matches("lea rax, [rip + .Lstr1]").count(), 1);
+        assert!(asm.ends_with(
+            ".section .rodata\n.Lstr0:\n  .string \"a\"\n.Lstr1:\n  .string \"b\\012\"\n"
+        ));
+        assert_eq!(asm.matches("print:").count(), 1);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(
+            compile_error("fn main() { let a = 1; a = 2; }"),
+            CompileError::AssignToImmutable {
+                name: "a".to_string(),
+                declared: token::Span::new(12, 21, 1, 13),
+                span: token::Span::new(23, 28, 1, 24),
+            }
+        );
+        assert!(matches!(
+            compile_error("fn f(a, b, c, d, e) { return a; } fn main() { return 0; }"),
+            CompileError::UnsupportedConstruct { .. }
+        ));
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        // The parser rejects this, so build the tree by hand.
+        let span = token::Span::new(12, 17, 1, 13);
+        let target = token::Span::new(12, 13, 1, 13);
+        let assign = ast::Expression::AssignExpression {
+            left: Box::new(ast::Expression::IntegerLiteral {
+                value: 1,
+                span: target,
+            }),
+            operator: "=".to_string(),
+            right: Box::new(ast::Expression::IntegerLiteral { value: 2, span }),
+            span,
+        };
+        let program = ast::Program {
+            functions: vec![ast::Function {
+                name: "main".to_string(),
+                parameters: Vec::new(),
+                body: ast::Statement::Block {
+                    statements: vec![ast::Statement::Expression {
+                        expression: assign,
+                        semicolon: true,
+                        span,
+                    }],
+                    span,
+                },
+                span,
+            }],
+        };
+
+        assert_eq!(
+            Compiler::new().compile_program(&program).unwrap_err(),
+            vec![CompileError::InvalidAssignmentTarget { span: target }]
+        );
+    }
+
+    #[test]
+    fn test_compile_errors_per_function() {
+        let errors = compile("fn f(x) { x = 1; } fn main() { let y = 1; y = 2; }").unwrap_err();
+        let names: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "cannot assign twice to immutable variable `x`".to_string(),
+                "cannot assign twice to immutable variable `y`".to_string()
+            ]
+        );
+    }
+}
+
 // #[cfg(test)]
 // mod evaluator_tests {
 //     use super::super::{lexer, parser};
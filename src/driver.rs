@@ -0,0 +1,476 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use rust::{ast, build, diagnostic, lexer, parser, render, source, token};
+
+pub const USAGE: &str = "\
+usage: rust [build|run] [options] <file>... [-- <args>...]
+       rust [build|run] [options] -e <program> [-- <args>...]
+
+Compiles the given source files, or `-` for standard input. By default the
+resulting assembly is printed; `build` produces an executable instead, and
+`run` builds the program, runs it with <args> and exits with its status.
+
+options:
+  -e <program>            compile <program> instead of reading a file
+  -o <path>               write the output to <path> (`a.out` for `build`)
+  --emit <kinds>          comma-separated outputs to produce, from `tokens`,
+                          `ast`, `ast-json`, `asm`, `obj` and `exe`; text is
+                          printed unless `-o` is given, `obj` is written to
+                          `a.o` and `exe` to `a.out`
+  --timeout <seconds>     kill the program after <seconds> (`run` only)
+  --memory-limit <bytes>  limit the program's address space; a `K`, `M` or
+                          `G` suffix may be used (`run` only)
+  -h, --help              print this message";
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    Asm,
+    Build,
+    Run,
+}
+
+/// An output of the compiler, in pipeline order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    AstJson,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl Emit {
+    fn parse(kind: &str) -> Option<Emit> {
+        match kind {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "ast-json" => Some(Emit::AstJson),
+            "asm" => Some(Emit::Asm),
+            "obj" => Some(Emit::Obj),
+            "exe" => Some(Emit::Exe),
+            _ => None,
+        }
+    }
+}
+
+pub enum Input {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+impl Input {
+    fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.clone(),
+            Input::Stdin => "<stdin>".to_string(),
+            Input::Inline(_) => "<input>".to_string(),
+        }
+    }
+
+    fn read(&self) -> io::Result<String> {
+        match self {
+            Input::File(path) => fs::read_to_string(path),
+            Input::Stdin => {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text)?;
+                Ok(text)
+            }
+            Input::Inline(text) => Ok(text.clone()),
+        }
+    }
+}
+
+pub struct Options {
+    pub mode: Mode,
+    pub inputs: Vec<Input>,
+    pub output: Option<String>,
+    /// What to produce, sorted and without duplicates. Empty in `run` mode.
+    pub emit: Vec<Emit>,
+    pub limits: build::Limits,
+    /// Arguments passed to the program in `run` mode.
+    pub args: Vec<String>,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            mode: Mode::Asm,
+            inputs: Vec::new(),
+            output: None,
+            emit: Vec::new(),
+            limits: build::Limits::default(),
+            args: Vec::new(),
+            help: false,
+        };
+
+        let mut args = args.iter().peekable();
+        match args.peek().map(|arg| arg.as_str()) {
+            Some("build") => options.mode = Mode::Build,
+            Some("run") => options.mode = Mode::Run,
+            _ => {}
+        }
+        if options.mode != Mode::Asm {
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => options.help = true,
+                "-e" => match args.next() {
+                    Some(program) => options.inputs.push(Input::Inline(program.clone())),
+                    None => return Err("`-e` requires a program".to_string()),
+                },
+                "-o" => match args.next() {
+                    Some(path) => options.output = Some(path.clone()),
+                    None => return Err("`-o` requires a path".to_string()),
+                },
+                "--emit" => match args.next() {
+                    Some(kinds) => options.add_emit(kinds)?,
+                    None => return Err("`--emit` requires a list of outputs".to_string()),
+                },
+                _ if arg.starts_with("--emit=") => options.add_emit(&arg["--emit=".len()..])?,
+                "--timeout" => match args
+                    .next()
+                    .and_then(|secs| secs.parse::<f64>().ok())
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                {
+                    Some(timeout) => options.limits.timeout = Some(timeout),
+                    None => return Err("`--timeout` requires a number of seconds".to_string()),
+                },
+                "--memory-limit" => match args.next().and_then(|size| parse_size(size)) {
+                    Some(bytes) => options.limits.memory = Some(bytes),
+                    None => return Err("`--memory-limit` requires a size in bytes".to_string()),
+                },
+                "--" => {
+                    options.args.extend(args.by_ref().cloned());
+                }
+                "-" => options.inputs.push(Input::Stdin),
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                _ => options.inputs.push(Input::File(arg.clone())),
+            }
+        }
+
+        if options.inputs.is_empty() && !options.help {
+            return Err("no input files".to_string());
+        }
+
+        if options.mode == Mode::Run && options.output.is_some() {
+            return Err("`-o` cannot be used with `run`".to_string());
+        }
+        if options.mode == Mode::Run && !options.emit.is_empty() {
+            return Err("`--emit` cannot be used with `run`".to_string());
+        }
+        if options.output.is_some() && options.emit.len() > 1 {
+            return Err("`-o` cannot be used with more than one `--emit` output".to_string());
+        }
+        if options.mode != Mode::Run {
+            if options.limits.timeout.is_some() {
+                return Err("`--timeout` can only be used with `run`".to_string());
+            }
+            if options.limits.memory.is_some() {
+                return Err("`--memory-limit` can only be used with `run`".to_string());
+            }
+            if !options.args.is_empty() {
+                return Err("program arguments can only be given to `run`".to_string());
+            }
+        }
+
+        if options.emit.is_empty() {
+            match options.mode {
+                Mode::Asm => options.emit.push(Emit::Asm),
+                Mode::Build => options.emit.push(Emit::Exe),
+                Mode::Run => {}
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn add_emit(&mut self, kinds: &str) -> Result<(), String> {
+        for kind in kinds.split(',') {
+            match Emit::parse(kind) {
+                Some(emit) => self.emit.push(emit),
+                None => return Err(format!("unknown `--emit` output `{}`", kind)),
+            }
+        }
+        self.emit.sort();
+        self.emit.dedup();
+
+        Ok(())
+    }
+
+    fn wants(&self, emit: Emit) -> bool {
+        self.emit.contains(&emit)
+    }
+}
+
+/// Parses a byte count with an optional `K`, `M` or `G` suffix.
+fn parse_size(size: &str) -> Option<u64> {
+    let (digits, unit) = match size.chars().last()? {
+        'K' | 'k' => (&size[..size.len() - 1], 1 << 10),
+        'M' | 'm' => (&size[..size.len() - 1], 1 << 20),
+        'G' | 'g' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Parses every input into a single program.
+pub fn parse(
+    options: &Options,
+    sources: &mut source::SourceMap,
+) -> (ast::Program, Vec<diagnostic::Diagnostic>) {
+    let mut program = ast::Program {
+        functions: Vec::new(),
+    };
+    let mut diagnostics = Vec::new();
+
+    for input in &options.inputs {
+        let text = match input.read() {
+            Ok(text) => text,
+            Err(err) => {
+                diagnostics.push(diagnostic::Diagnostic::error(
+                    "E0300",
+                    format!("couldn't read `{}`: {}", input.name(), err),
+                ));
+                continue;
+            }
+        };
+
+        let file = sources.add(input.name(), text);
+        let l = lexer::Lexer::with_offset(&file.text, file.offset);
+        let mut p = parser::Parser::new(l);
+        program.functions.append(&mut p.parse_program().functions);
+        diagnostics.append(&mut p.errors);
+    }
+
+    (program, diagnostics)
+}
+
+/// Lists the tokens of every source file, one per line.
+fn dump_tokens(sources: &source::SourceMap) -> String {
+    let mut s = String::new();
+    for file in &sources.files {
+        let mut l = lexer::Lexer::with_offset(&file.text, file.offset);
+        loop {
+            let tok = l.next_token();
+            s += &format!(
+                "{}:{}\t{:?}\t{:?}\n",
+                file.name, tok.span, tok.token_type, tok.literal
+            );
+            if tok.token_type == token::TokenType::EoF {
+                break;
+            }
+        }
+    }
+
+    s
+}
+
+/// Prints `text`, or writes it to the `-o` path if there is one.
+fn write_text(options: &Options, text: &str) -> Result<(), Box<diagnostic::Diagnostic>> {
+    match &options.output {
+        Some(path) => fs::write(path, text).map_err(|err| {
+            Box::new(diagnostic::Diagnostic::error(
+                "E0301",
+                format!("couldn't write `{}`: {}", path, err),
+            ))
+        }),
+        None => {
+            println!("{}", text.trim_end_matches(&['\r', '\n'][..]));
+            Ok(())
+        }
+    }
+}
+
+pub fn run(options: &Options) -> i32 {
+    let mut sources = source::SourceMap::new();
+    let (program, mut diagnostics) = parse(options, &mut sources);
+
+    // Tokens are dumped even if parsing failed, since that is usually why
+    // they are wanted.
+    if options.wants(Emit::Tokens) {
+        if let Err(d) = write_text(options, &dump_tokens(&sources)) {
+            diagnostics.push(*d);
+        }
+    }
+    if !diagnostics.is_empty() {
+        report(&diagnostics, &sources);
+        return 1;
+    }
+
+    match emit(options, program) {
+        Ok(code) => code,
+        Err(diagnostics) => {
+            report(&diagnostics, &sources);
+            if diagnostics.iter().any(|d| d.code == TIMED_OUT) {
+                124
+            } else {
+                1
+            }
+        }
+    }
+}
+
+const TIMED_OUT: &str = "E0305";
+
+/// Produces every requested output after the token dump, and runs the
+/// program in `run` mode. Returns the exit status of the driver.
+fn emit(options: &Options, program: ast::Program) -> Result<i32, Vec<diagnostic::Diagnostic>> {
+    if options.wants(Emit::Ast) {
+        write_text(options, &program.to_string()).map_err(|d| vec![*d])?;
+    }
+    if options.wants(Emit::AstJson) {
+        write_text(options, &program.to_json().to_string()).map_err(|d| vec![*d])?;
+    }
+
+    let needs_asm = options.mode == Mode::Run
+        || options.wants(Emit::Asm)
+        || options.wants(Emit::Obj)
+        || options.wants(Emit::Exe);
+    if !needs_asm {
+        return Ok(0);
+    }
+
+    let asm = match rust::compile(&program, &rust::Options::default())? {
+        rust::Artifact::Asm(asm) => asm,
+        _ => unreachable!("assembly was requested"),
+    };
+
+    if options.wants(Emit::Asm) {
+        write_text(options, &asm).map_err(|d| vec![*d])?;
+    }
+    if options.wants(Emit::Obj) {
+        let output = options.output.as_deref().unwrap_or("a.o");
+        build::assemble(&asm, Path::new(output)).map_err(|d| vec![*d])?;
+    }
+    if options.wants(Emit::Exe) {
+        let output = options.output.as_deref().unwrap_or("a.out");
+        build::link(&asm, Path::new(output)).map_err(|d| vec![*d])?;
+    }
+
+    if options.mode != Mode::Run {
+        return Ok(0);
+    }
+    match build::run(&asm, &options.args, &options.limits).map_err(|d| vec![*d])? {
+        build::Outcome::Exited(code) => Ok(code),
+        // Follow the shell convention for programs killed by a signal.
+        build::Outcome::Signaled(signal) => Ok(128 + signal),
+        build::Outcome::TimedOut => {
+            let timeout = options.limits.timeout.unwrap_or_default();
+            let d = diagnostic::Diagnostic::error(
+                TIMED_OUT,
+                format!("program timed out after {:?}", timeout),
+            )
+            .with_note("the program was killed; use `--timeout` to allow it more time");
+            Err(vec![d])
+        }
+    }
+}
+
+pub fn report(diagnostics: &[diagnostic::Diagnostic], sources: &source::SourceMap) {
+    for d in diagnostics {
+        eprintln!("{}", render::render(d, sources));
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 {
+        eprintln!(
+            "error: could not compile due to {} previous error{}",
+            errors,
+            if errors == 1 { "" } else { "s" }
+        );
+    }
+}
+
+#[cfg(test)]
+mod driver_tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse(&["a.src", "-", "-e", "fn main() {}"]).unwrap();
+        let names: Vec<String> = options.inputs.iter().map(|input| input.name()).collect();
+        assert_eq!(names, vec!["a.src", "<stdin>", "<input>"]);
+        assert_eq!(options.mode, Mode::Asm);
+        assert_eq!(options.output, None);
+        assert_eq!(options.emit, vec![Emit::Asm]);
+
+        let options = parse(&["a.src", "--emit=asm,tokens", "--emit", "ast-json,asm"]).unwrap();
+        assert_eq!(options.emit, vec![Emit::Tokens, Emit::AstJson, Emit::Asm]);
+
+        let options = parse(&["build", "a.src", "-o", "a"]).unwrap();
+        assert_eq!(options.mode, Mode::Build);
+        assert_eq!(options.output, Some("a".to_string()));
+        assert_eq!(options.emit, vec![Emit::Exe]);
+
+        let options = parse(&[
+            "run",
+            "a.src",
+            "--timeout",
+            "1.5",
+            "--memory-limit",
+            "64M",
+            "--",
+            "x",
+            "-o",
+        ])
+        .unwrap();
+        assert_eq!(options.mode, Mode::Run);
+        assert_eq!(options.limits.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(options.limits.memory, Some(64 << 20));
+        assert_eq!(options.args, vec!["x", "-o"]);
+        assert!(options.emit.is_empty());
+
+        assert!(parse(&["--help"]).unwrap().help);
+        assert_eq!(parse(&[]).err().unwrap(), "no input files");
+        assert_eq!(parse(&["-e"]).err().unwrap(), "`-e` requires a program");
+        assert_eq!(parse(&["-x"]).err().unwrap(), "unknown option `-x`");
+        assert_eq!(
+            parse(&["a.src", "-o"]).err().unwrap(),
+            "`-o` requires a path"
+        );
+        assert_eq!(
+            parse(&["a.src", "--emit=asm,ir"]).err().unwrap(),
+            "unknown `--emit` output `ir`"
+        );
+        assert_eq!(
+            parse(&["a.src", "--emit=asm,obj", "-o", "a"])
+                .err()
+                .unwrap(),
+            "`-o` cannot be used with more than one `--emit` output"
+        );
+        assert_eq!(
+            parse(&["run", "a.src", "-o", "a"]).err().unwrap(),
+            "`-o` cannot be used with `run`"
+        );
+        assert_eq!(
+            parse(&["a.src", "--timeout", "1"]).err().unwrap(),
+            "`--timeout` can only be used with `run`"
+        );
+        for secs in ["soon", "-1", "inf", "1e300"] {
+            assert_eq!(
+                parse(&["run", "a.src", "--timeout", secs]).err().unwrap(),
+                "`--timeout` requires a number of seconds"
+            );
+        }
+        assert_eq!(
+            parse(&["run", "a.src", "--memory-limit", "lots"])
+                .err()
+                .unwrap(),
+            "`--memory-limit` requires a size in bytes"
+        );
+    }
+}
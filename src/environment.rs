@@ -1,8 +1,13 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use super::token;
+
 #[derive(Debug)]
 pub struct Variable {
     pub offset: usize,
+    pub mutable: bool,
+    /// Where the variable was declared.
+    pub span: token::Span,
 }
 
 pub static REGISTERS: [&str; 4] = ["rcx", "rdx", "r8", "r9"];
@@ -11,18 +16,16 @@ pub static REGISTERS: [&str; 4] = ["rcx", "rdx", "r8", "r9"];
 pub struct Environment {
     pub store: HashMap<String, Rc<Variable>>,
     pub offset: usize,
-    pub label_count: usize,
     pub stack: usize,
     pub outer: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
-    pub fn new(offset: usize, stack: usize, label_count: usize) -> Environment {
+    pub fn new(offset: usize, stack: usize) -> Environment {
         Environment {
             store: HashMap::new(),
             offset,
             stack,
-            label_count,
             outer: None,
         }
     }
@@ -48,55 +51,35 @@ impl Environment {
         }
     }
 
-    pub fn set(&mut self, name: &str) {
+    /// Declares `name` in this scope with a fresh stack slot. An existing
+    /// variable of the same name, here or in an outer scope, is shadowed.
+    pub fn set(&mut self, name: &str, mutable: bool, span: token::Span) {
         self.offset += 8;
         self.store.insert(
             name.to_string(),
             Rc::new(Variable {
                 offset: self.offset,
+                mutable,
+                span,
             }),
         );
     }
 
-    pub fn contains_key(&self, name: &str) -> bool {
-        let stack = self.stack;
-        self.contains_key_with_stack(name, stack)
-    }
-
-    pub fn contains_key_with_stack(&self, name: &str, stack: usize) -> bool {
-        if self.store.contains_key(name) {
-            true
-        } else {
-            match &self.outer {
-                Some(out_env) => {
-                    if out_env.borrow().stack == stack {
-                        out_env.borrow_mut().contains_key_with_stack(name, stack)
-                    } else {
-                        false
-                    }
-                }
-                None => false,
-            }
-        }
-    }
-
-    pub fn inc_label_count(&mut self) -> usize {
-        self.label_count += 1;
-        self.label_count
+    /// Allocates a stack slot in this scope that no name refers to, and
+    /// returns its offset.
+    pub fn reserve(&mut self) -> usize {
+        self.offset += 8;
+        self.offset
     }
 
     pub fn new_block_env(outer: Rc<RefCell<Environment>>) -> Environment {
-        let mut env = Environment::new(
-            outer.borrow().offset,
-            outer.borrow().stack,
-            outer.borrow().label_count,
-        );
+        let mut env = Environment::new(outer.borrow().offset, outer.borrow().stack);
         env.outer = Some(outer);
         env
     }
 
     pub fn new_fn_env(outer: Rc<RefCell<Environment>>) -> Environment {
-        let mut env = Environment::new(0, outer.borrow().stack + 1, outer.borrow().label_count);
+        let mut env = Environment::new(0, outer.borrow().stack + 1);
         env.outer = Some(outer);
 
         env
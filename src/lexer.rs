@@ -1,127 +1,259 @@
-use super::token;
+use super::{diagnostic, token};
 
+/// Turns source text into tokens.
+///
+/// `position` and `read_position` are byte offsets into `input`, of the
+/// current character and of the one after it, so every character is visited
+/// once and lexing takes time linear in the length of the input.
 pub struct Lexer {
     pub input: String,
     position: usize,
     read_position: usize,
     ch: char,
+    base: usize,
+    line: usize,
+    column: usize,
+    pub errors: Vec<diagnostic::Diagnostic>,
 }
 
 #[allow(dead_code)]
 impl Lexer {
     pub fn new(input: &str) -> Lexer {
-        let mut l = Lexer {
+        Lexer::with_offset(input, 0)
+    }
+
+    /// Creates a lexer whose spans start at byte `base` instead of zero, so
+    /// that spans from several source files never overlap.
+    pub fn with_offset(input: &str, base: usize) -> Lexer {
+        let ch = input.chars().next().unwrap_or('\0');
+        Lexer {
             input: input.to_string(),
             position: 0,
-            read_position: 0,
-            ch: 'a',
-        };
-        l.read_char();
-
-        l
+            read_position: ch.len_utf8(),
+            ch,
+            base,
+            line: 1,
+            column: 1,
+            errors: Vec::new(),
+        }
     }
 
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = '\0';
+        if self.position >= self.input.len() {
+            return;
+        }
+
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            self.ch = self.input.chars().nth(self.read_position).unwrap();
+            self.column += 1;
         }
+
         self.position = self.read_position;
-        self.read_position += 1;
+        self.ch = self.char_at(self.position);
+        self.read_position = self.position + self.ch.len_utf8();
+    }
+
+    /// Returns the character starting at byte `offset`, or `'\0'` past the end.
+    fn char_at(&self, offset: usize) -> char {
+        self.input
+            .get(offset..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0')
     }
 
     pub fn next_token(&mut self) -> token::Token {
-        self.skip_whitespace();
+        let leading_trivia = self.read_trivia();
 
-        let tok = match self.ch {
-            '+' => token::new_token(token::TokenType::Plus, self.ch.to_string()),
-            '-' => token::new_token(token::TokenType::Minus, self.ch.to_string()),
-            '*' => token::new_token(token::TokenType::Asterisk, self.ch.to_string()),
-            '/' => token::new_token(token::TokenType::Slash, self.ch.to_string()),
+        let mut tok = self.read_token();
+        tok.leading_trivia = leading_trivia;
+
+        tok
+    }
+
+    fn read_token(&mut self) -> token::Token {
+        let span = self.start_span();
+
+        let (token_type, literal) = match self.ch {
+            '+' => self.read_compound(token::TokenType::Plus, token::TokenType::PlusAssign),
+            '-' => self.read_compound(token::TokenType::Minus, token::TokenType::MinusAssign),
+            '*' => self.read_compound(token::TokenType::Asterisk, token::TokenType::AsteriskAssign),
+            '/' => self.read_compound(token::TokenType::Slash, token::TokenType::SlashAssign),
+            '%' => self.read_compound(token::TokenType::Percent, token::TokenType::PercentAssign),
+            '^' => self.read_compound(token::TokenType::Caret, token::TokenType::CaretAssign),
             '=' => {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    token::new_token(token::TokenType::Eq, ch.to_string() + &self.ch.to_string())
+                    (token::TokenType::Eq, ch.to_string() + &self.ch.to_string())
                 } else {
-                    token::new_token(token::TokenType::Assign, self.ch.to_string())
+                    (token::TokenType::Assign, self.ch.to_string())
                 }
             }
             '<' => {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    token::new_token(
+                    (
                         token::TokenType::LtEq,
                         ch.to_string() + &self.ch.to_string(),
                     )
+                } else if self.peek_char() == '<' {
+                    let ch = self.ch;
+                    self.read_char();
+                    let (token_type, literal) =
+                        self.read_compound(token::TokenType::Shl, token::TokenType::ShlAssign);
+                    (token_type, ch.to_string() + &literal)
                 } else {
-                    token::new_token(token::TokenType::Lt, self.ch.to_string())
+                    (token::TokenType::Lt, self.ch.to_string())
                 }
             }
             '>' => {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    token::new_token(
+                    (
                         token::TokenType::GtEq,
                         ch.to_string() + &self.ch.to_string(),
                     )
+                } else if self.peek_char() == '>' {
+                    let ch = self.ch;
+                    self.read_char();
+                    let (token_type, literal) =
+                        self.read_compound(token::TokenType::Shr, token::TokenType::ShrAssign);
+                    (token_type, ch.to_string() + &literal)
                 } else {
-                    token::new_token(token::TokenType::Gt, self.ch.to_string())
+                    (token::TokenType::Gt, self.ch.to_string())
                 }
             }
             '!' => {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    token::new_token(
+                    (
                         token::TokenType::NotEq,
                         ch.to_string() + &self.ch.to_string(),
                     )
                 } else {
-                    // token::new_token(token::TokenType::BANG, self.ch.to_string())
-                    token::new_token(token::TokenType::Illegal, self.ch.to_string())
+                    (token::TokenType::Bang, self.ch.to_string())
+                }
+            }
+            '&' => {
+                if self.peek_char() == '&' {
+                    let ch = self.ch;
+                    self.read_char();
+                    (token::TokenType::And, ch.to_string() + &self.ch.to_string())
+                } else {
+                    self.read_compound(
+                        token::TokenType::Ampersand,
+                        token::TokenType::AmpersandAssign,
+                    )
+                }
+            }
+            '|' => {
+                if self.peek_char() == '|' {
+                    let ch = self.ch;
+                    self.read_char();
+                    (token::TokenType::Or, ch.to_string() + &self.ch.to_string())
+                } else {
+                    self.read_compound(token::TokenType::Pipe, token::TokenType::PipeAssign)
                 }
             }
-            ',' => token::new_token(token::TokenType::Comma, self.ch.to_string()),
-            ';' => token::new_token(token::TokenType::SemiColon, self.ch.to_string()),
-            '(' => token::new_token(token::TokenType::LParen, self.ch.to_string()),
-            ')' => token::new_token(token::TokenType::RParen, self.ch.to_string()),
-            '{' => token::new_token(token::TokenType::LBrace, self.ch.to_string()),
-            '}' => token::new_token(token::TokenType::RBrace, self.ch.to_string()),
-            // '[' => token::new_token(token::TokenType::LBRACKET, self.ch.to_string()),
-            // ']' => token::new_token(token::TokenType::RBRACKET, self.ch.to_string()),
-            // '"' => token::new_token(token::TokenType::STRING, self.read_string()),
-            // ':' => token::new_token(token::TokenType::COLON, self.ch.to_string()),
-            // '.' => token::new_token(token::TokenType::DOT, self.ch.to_string()),
-            '\0' => token::Token {
-                token_type: token::TokenType::EoF,
-                literal: String::from(""),
-            },
+            '~' => (token::TokenType::Tilde, self.ch.to_string()),
+            ',' => (token::TokenType::Comma, self.ch.to_string()),
+            ';' => (token::TokenType::SemiColon, self.ch.to_string()),
+            '(' => (token::TokenType::LParen, self.ch.to_string()),
+            ')' => (token::TokenType::RParen, self.ch.to_string()),
+            '{' => (token::TokenType::LBrace, self.ch.to_string()),
+            '}' => (token::TokenType::RBrace, self.ch.to_string()),
+            // '[' => (token::TokenType::LBRACKET, self.ch.to_string()),
+            // ']' => (token::TokenType::RBRACKET, self.ch.to_string()),
+            '"' => (token::TokenType::String, self.read_string()),
+            '\'' if self.at_label() => {
+                self.read_char();
+                let literal = format!("'{}", self.read_identifier());
+
+                return token::new_token(token::TokenType::Label, literal, self.end_span(span));
+            }
+            '\'' => (token::TokenType::Char, self.read_char_literal()),
+            ':' => (token::TokenType::Colon, self.ch.to_string()),
+            // '.' => (token::TokenType::DOT, self.ch.to_string()),
+            '\0' => (token::TokenType::EoF, String::from("")),
             _ => {
-                if self.ch.is_alphabetic() {
+                if token::is_ident_start(self.ch) {
                     let literal = self.read_identifier();
                     let token_type = token::lookup_ident(&literal);
 
-                    return token::new_token(token_type, literal);
+                    return token::new_token(token_type, literal, self.end_span(span));
                 } else if self.ch.is_numeric() {
-                    return token::new_token(token::TokenType::Int, self.read_number());
+                    let literal = self.read_number();
+
+                    return token::new_token(token::TokenType::Int, literal, self.end_span(span));
                 } else {
-                    token::new_token(token::TokenType::Illegal, self.ch.to_string())
+                    (token::TokenType::Illegal, self.ch.to_string())
                 }
             }
         };
 
         self.read_char();
 
+        let tok = token::new_token(token_type, literal, self.end_span(span));
+        if tok.token_type == token::TokenType::Illegal {
+            self.illegal_char_error(&tok);
+        }
+
         tok
     }
 
+    /// Reads an operator that becomes a compound assignment when followed by
+    /// `=`, such as `+` and `+=`.
+    fn read_compound(
+        &mut self,
+        token_type: token::TokenType,
+        assign_type: token::TokenType,
+    ) -> (token::TokenType, String) {
+        if self.peek_char() == '=' {
+            let ch = self.ch;
+            self.read_char();
+            (assign_type, ch.to_string() + &self.ch.to_string())
+        } else {
+            (token_type, self.ch.to_string())
+        }
+    }
+
+    fn illegal_char_error(&mut self, tok: &token::Token) {
+        self.errors.push(
+            diagnostic::Diagnostic::error(
+                "E0001",
+                format!("unexpected character `{}`", tok.literal),
+            )
+            .with_primary(tok.span, "not valid here"),
+        );
+    }
+
+    /// Returns an empty span positioned at the current character.
+    fn start_span(&self) -> token::Span {
+        let offset = self.base + self.position;
+        token::Span::new(offset, offset, self.line, self.column)
+    }
+
+    /// Extends `span` up to the current character.
+    fn end_span(&self, span: token::Span) -> token::Span {
+        token::Span::new(
+            span.start,
+            self.base + self.position,
+            span.line,
+            span.column,
+        )
+    }
+
+    /// Reads a number along with any letters and underscores that follow
+    /// it, such as a radix prefix; the parser checks that the digits are
+    /// valid.
     fn read_number(&mut self) -> String {
         let position = self.position;
-        while self.ch.is_numeric() {
+        while token::is_ident_continue(self.ch) {
             self.read_char();
         }
 
@@ -130,35 +262,196 @@ impl Lexer {
 
     fn read_identifier(&mut self) -> String {
         let position = self.position;
-        while self.ch.is_alphabetic() {
+        while token::is_ident_continue(self.ch) {
             self.read_char();
         }
 
         self.get_slice(position, self.position)
     }
 
-    // fn read_string(&mut self) -> String {
-    //     let position = self.position + 1;
-    //     loop {
-    //         self.read_char();
-    //         if self.ch == '"' || self.ch == '\0' {
-    //             break;
-    //         }
-    //     }
+    /// Whether the quote at the current character starts a label such as
+    /// `'outer` rather than a character literal: a label is a quote followed
+    /// by an identifier that is not closed by another quote.
+    fn at_label(&self) -> bool {
+        if !token::is_ident_start(self.peek_char()) {
+            return false;
+        }
 
-    //     return self.get_slice(position, self.position);
-    // }
+        let mut offset = self.read_position;
+        while token::is_ident_continue(self.char_at(offset)) {
+            offset += self.char_at(offset).len_utf8();
+        }
 
-    fn get_slice(&self, start: usize, end: usize) -> String {
-        let begin = self.input.char_indices().nth(start).unwrap().0;
-        if let Some(end) = self.input.char_indices().nth(end) {
-            let str2 = &self.input[begin..end.0];
-            return String::from(str2);
+        self.char_at(offset) != '\''
+    }
+
+    /// Reads a string literal starting at its opening quote and returns its
+    /// value with escapes resolved. Stops at the closing quote.
+    fn read_string(&mut self) -> String {
+        let span = self.start_span();
+        let mut value = String::new();
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                '\0' => {
+                    let opening =
+                        token::Span::new(span.start, span.start + 1, span.line, span.column);
+                    self.errors.push(
+                        diagnostic::Diagnostic::error(
+                            "E0003",
+                            "unterminated string literal".to_string(),
+                        )
+                        .with_primary(opening, "string starts here"),
+                    );
+                    break;
+                }
+                '\\' => {
+                    if let Some(c) = self.read_escape() {
+                        value.push(c);
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+
+        value
+    }
+
+    /// Reads a character literal starting at its opening quote and returns
+    /// the character, or nothing if the literal is invalid. Stops at the
+    /// closing quote.
+    fn read_char_literal(&mut self) -> String {
+        let span = self.start_span();
+        self.read_char();
+
+        // `None` if there is no character at all, and `Some(None)` if there
+        // is an invalid escape, which `read_escape` has already reported.
+        let c = match self.ch {
+            '\\' => Some(self.read_escape()),
+            '\'' | '\n' | '\0' => None,
+            c => Some(Some(c)),
+        };
+
+        if let Some(c) = c {
+            if self.peek_char() == '\'' {
+                self.read_char();
+                return c.map(String::from).unwrap_or_default();
+            }
+        }
+
+        let rest = &self.input[self.position..];
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        let d = if c.is_none() && self.ch == '\'' {
+            let end = self.base + self.position + 1;
+            diagnostic::Diagnostic::error("E0005", "empty character literal".to_string())
+                .with_primary(
+                    token::Span::new(span.start, end, span.line, span.column),
+                    "empty character literal",
+                )
+        } else if let (Some(_), Some(close)) = (c, line.find('\'')) {
+            // Skip to the closing quote so that the rest is not lexed as
+            // code.
+            let close = self.position + close;
+            while self.position < close {
+                self.read_char();
+            }
+            let end = self.base + self.position + 1;
+            diagnostic::Diagnostic::error(
+                "E0005",
+                "character literal may only contain one character".to_string(),
+            )
+            .with_primary(
+                token::Span::new(span.start, end, span.line, span.column),
+                "more than one character",
+            )
+        } else {
+            let opening = token::Span::new(span.start, span.start + 1, span.line, span.column);
+            diagnostic::Diagnostic::error("E0005", "unterminated character literal".to_string())
+                .with_primary(opening, "character literal starts here")
+        };
+        self.errors.push(d.with_note(
+            "character literals contain exactly one character, like `'a'`; use `\"` for strings",
+        ));
+
+        String::new()
+    }
+
+    /// Reads an escape sequence starting at its backslash. Stops at the last
+    /// character of the sequence.
+    fn read_escape(&mut self) -> Option<char> {
+        let span = self.start_span();
+        self.read_char();
+
+        let c = match self.ch {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            'x' => {
+                let mut value = 0;
+                let mut digits = 0;
+                while digits < 2 && self.peek_char().is_ascii_hexdigit() {
+                    self.read_char();
+                    value = value * 16 + self.ch.to_digit(16).unwrap();
+                    digits += 1;
+                }
+                if digits == 2 && value <= 0x7f {
+                    char::from_u32(value)
+                } else {
+                    None
+                }
+            }
+            'u' if self.peek_char() == '{' => {
+                self.read_char();
+                let mut value = 0;
+                let mut digits = 0;
+                while digits < 6 && self.peek_char().is_ascii_hexdigit() {
+                    self.read_char();
+                    value = value * 16 + self.ch.to_digit(16).unwrap();
+                    digits += 1;
+                }
+                if digits > 0 && self.peek_char() == '}' {
+                    self.read_char();
+                    char::from_u32(value)
+                } else {
+                    None
+                }
+            }
+            // Reached the end of the input, which the caller reports.
+            '\0' => return None,
+            _ => None,
+        };
+
+        if c.is_none() {
+            let end = self.base + self.position + self.ch.len_utf8();
+            let span = token::Span::new(span.start, end, span.line, span.column);
+            let escape = &self.input[span.start - self.base..end - self.base];
+            let note = if escape.starts_with("\\x") {
+                "`\\x` must be followed by two hexadecimal digits no greater than `7F`"
+            } else if escape.starts_with("\\u") {
+                "`\\u` must be followed by a Unicode code point of up to six hexadecimal digits in braces, like `\\u{1F600}`"
+            } else {
+                "valid escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\\"`, `\\'`, `\\x` and `\\u`"
+            };
+            self.errors.push(
+                diagnostic::Diagnostic::error(
+                    "E0004",
+                    format!("invalid escape sequence `{}`", escape),
+                )
+                .with_primary(span, "invalid escape")
+                .with_note(note),
+            );
         }
 
-        let str2 = &self.input[begin..];
+        c
+    }
 
-        String::from(str2)
+    fn get_slice(&self, start: usize, end: usize) -> String {
+        String::from(&self.input[start..end])
     }
 
     fn skip_whitespace(&mut self) {
@@ -167,15 +460,358 @@ impl Lexer {
         }
     }
 
-    fn peek_char(&mut self) -> char {
-        if self.read_position >= self.input.len() {
-            '\0'
+    /// Skips whitespace and collects the comments in it.
+    fn read_trivia(&mut self) -> Vec<token::Trivia> {
+        let mut trivia = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match (self.ch, self.peek_char()) {
+                ('/', '/') => trivia.push(self.read_line_comment()),
+                ('/', '*') => trivia.push(self.read_block_comment()),
+                _ => return trivia,
+            }
+        }
+    }
+
+    fn read_line_comment(&mut self) -> token::Trivia {
+        let span = self.start_span();
+        let mut text = String::new();
+        while self.ch != '\n' && self.ch != '\0' {
+            text.push(self.ch);
+            self.read_char();
+        }
+
+        token::Trivia {
+            kind: token::TriviaKind::LineComment,
+            text,
+            span: self.end_span(span),
+        }
+    }
+
+    /// Reads a block comment, which may contain other block comments.
+    fn read_block_comment(&mut self) -> token::Trivia {
+        let span = self.start_span();
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => depth += 1,
+                ('*', '/') => depth -= 1,
+                ('\0', _) => {
+                    let opening =
+                        token::Span::new(span.start, span.start + 2, span.line, span.column);
+                    self.errors.push(
+                        diagnostic::Diagnostic::error(
+                            "E0002",
+                            "unterminated block comment".to_string(),
+                        )
+                        .with_primary(opening, "comment starts here")
+                        .with_note("block comments nest, so every `/*` needs its own `*/`"),
+                    );
+                    break;
+                }
+                _ => {
+                    text.push(self.ch);
+                    self.read_char();
+                    continue;
+                }
+            }
+
+            // Both characters of a delimiter are consumed together so that
+            // `/*/` does not count as an opening and a closing.
+            text.push(self.ch);
+            self.read_char();
+            text.push(self.ch);
+            self.read_char();
+            if depth == 0 {
+                break;
+            }
+        }
+
+        token::Trivia {
+            kind: token::TriviaKind::BlockComment,
+            text,
+            span: self.end_span(span),
+        }
+    }
+
+    fn peek_char(&self) -> char {
+        self.char_at(self.read_position)
+    }
+}
+
+/// Yields every token up to, but not including, the end of the input.
+///
+/// Comments after the last token are attached to the end-of-file token and
+/// so are only available through `next_token`.
+impl Iterator for Lexer {
+    type Item = token::Token;
+
+    fn next(&mut self) -> Option<token::Token> {
+        let tok = self.next_token();
+        if tok.token_type == token::TokenType::EoF {
+            None
         } else {
-            self.input.chars().nth(self.read_position).unwrap()
+            Some(tok)
         }
     }
 }
 
+#[cfg(test)]
+mod lexer_tests {
+    use super::*;
+
+    #[test]
+    fn test_token_spans() {
+        let input = "fn main() {\n  xy = 10;\n}";
+        let tests = [
+            (token::TokenType::Function, "fn", 0, 2, 1, 1),
+            (token::TokenType::Ident, "main", 3, 7, 1, 4),
+            (token::TokenType::LParen, "(", 7, 8, 1, 8),
+            (token::TokenType::RParen, ")", 8, 9, 1, 9),
+            (token::TokenType::LBrace, "{", 10, 11, 1, 11),
+            (token::TokenType::Ident, "xy", 14, 16, 2, 3),
+            (token::TokenType::Assign, "=", 17, 18, 2, 6),
+            (token::TokenType::Int, "10", 19, 21, 2, 8),
+            (token::TokenType::SemiColon, ";", 21, 22, 2, 10),
+            (token::TokenType::RBrace, "}", 23, 24, 3, 1),
+            (token::TokenType::EoF, "", 24, 24, 3, 2),
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for (token_type, literal, start, end, line, column) in tests {
+            let tok = l.next_token();
+
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+            assert_eq!(tok.span, token::Span::new(start, end, line, column));
+            assert_eq!(&input[start..end], literal);
+        }
+    }
+
+    #[test]
+    fn test_iterator() {
+        let input = "é = café; // ü\nx";
+        let tokens: Vec<token::Token> = Lexer::new(input).collect();
+        let tests = [
+            (token::TokenType::Ident, "é", 0, 2, 1, 1),
+            (token::TokenType::Assign, "=", 3, 4, 1, 3),
+            (token::TokenType::Ident, "café", 5, 10, 1, 5),
+            (token::TokenType::SemiColon, ";", 10, 11, 1, 9),
+            (token::TokenType::Ident, "x", 18, 19, 2, 1),
+        ];
+
+        assert_eq!(tokens.len(), tests.len());
+        for (tok, (token_type, literal, start, end, line, column)) in tokens.iter().zip(tests) {
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+            assert_eq!(tok.span, token::Span::new(start, end, line, column));
+            assert_eq!(&input[start..end], literal);
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = "+= - -= & && &= | || |= ^ ^= < << <<= <= > >> >>= >= %= */=";
+        let tests = [
+            (token::TokenType::PlusAssign, "+="),
+            (token::TokenType::Minus, "-"),
+            (token::TokenType::MinusAssign, "-="),
+            (token::TokenType::Ampersand, "&"),
+            (token::TokenType::And, "&&"),
+            (token::TokenType::AmpersandAssign, "&="),
+            (token::TokenType::Pipe, "|"),
+            (token::TokenType::Or, "||"),
+            (token::TokenType::PipeAssign, "|="),
+            (token::TokenType::Caret, "^"),
+            (token::TokenType::CaretAssign, "^="),
+            (token::TokenType::Lt, "<"),
+            (token::TokenType::Shl, "<<"),
+            (token::TokenType::ShlAssign, "<<="),
+            (token::TokenType::LtEq, "<="),
+            (token::TokenType::Gt, ">"),
+            (token::TokenType::Shr, ">>"),
+            (token::TokenType::ShrAssign, ">>="),
+            (token::TokenType::GtEq, ">="),
+            (token::TokenType::PercentAssign, "%="),
+            (token::TokenType::Asterisk, "*"),
+            (token::TokenType::SlashAssign, "/="),
+        ];
+
+        let tokens: Vec<token::Token> = Lexer::new(input).collect();
+        assert_eq!(tokens.len(), tests.len());
+        for (tok, (token_type, literal)) in tokens.iter().zip(tests) {
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let input = "x1 fib_iter _tmp __ 1a while_ whilex while";
+        let tests = [
+            (token::TokenType::Ident, "x1"),
+            (token::TokenType::Ident, "fib_iter"),
+            (token::TokenType::Ident, "_tmp"),
+            (token::TokenType::Ident, "__"),
+            (token::TokenType::Int, "1a"),
+            (token::TokenType::Ident, "while_"),
+            (token::TokenType::Ident, "whilex"),
+            (token::TokenType::While, "while"),
+        ];
+
+        let tokens: Vec<token::Token> = Lexer::new(input).collect();
+        assert_eq!(tokens.len(), tests.len());
+        for (tok, (token_type, literal)) in tokens.iter().zip(tests) {
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let input = "0xFF_ff 0b1010 0o17 1_000 12ab 0x";
+        let literals: Vec<String> = Lexer::new(input).map(|tok| tok.literal).collect();
+        assert_eq!(
+            literals,
+            vec!["0xFF_ff", "0b1010", "0o17", "1_000", "12ab", "0x"]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let input = r#""a\tb\n\"\\\x41" "" "x\q\x80y""#;
+        let mut l = Lexer::new(input);
+
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::String);
+        assert_eq!(tok.literal, "a\tb\n\"\\A");
+        assert_eq!(tok.span, token::Span::new(0, 16, 1, 1));
+
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::String);
+        assert_eq!(tok.literal, "");
+
+        let tok = l.next_token();
+        assert_eq!(tok.literal, "xy");
+        let errors: Vec<(&str, usize, usize)> = l
+            .errors
+            .iter()
+            .map(|d| {
+                let span = d.primary.as_ref().unwrap().span;
+                (d.code, span.start, span.end)
+            })
+            .collect();
+        assert_eq!(errors, vec![("E0004", 22, 24), ("E0004", 24, 28)]);
+
+        let mut l = Lexer::new("1 \"abc");
+        l.next_token();
+        let tok = l.next_token();
+        assert_eq!(tok.literal, "abc");
+        assert_eq!(l.next_token().token_type, token::TokenType::EoF);
+        assert_eq!(l.errors.len(), 1);
+        assert_eq!(l.errors[0].code, "E0003");
+    }
+
+    #[test]
+    fn test_chars() {
+        let input = r"'a' '\n' '\'' '\u{1F600}' 'é'";
+        let literals: Vec<String> = Lexer::new(input).map(|tok| tok.literal).collect();
+        assert_eq!(literals, vec!["a", "\n", "'", "\u{1F600}", "é"]);
+
+        let tests = [
+            ("''", "E0005", 0, 2),
+            ("'ab'", "E0005", 0, 4),
+            ("'\\nb'", "E0005", 0, 5),
+            ("'1", "E0005", 0, 1),
+            (r"'\u{110000}'", "E0004", 1, 11),
+            (r"'\u{}'", "E0004", 1, 4),
+        ];
+        for (input, code, start, end) in tests {
+            let mut l = Lexer::new(input);
+            let tok = l.next_token();
+            assert_eq!(tok.token_type, token::TokenType::Char, "{}", input);
+            assert_eq!(tok.literal, "", "{}", input);
+            assert_eq!(l.errors[0].code, code, "{}", input);
+            let span = l.errors[0].primary.as_ref().unwrap().span;
+            assert_eq!((span.start, span.end), (start, end), "{}", input);
+            assert_eq!(
+                l.next_token().token_type,
+                token::TokenType::EoF,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        let input = "'outer: 'a' 'a 'é";
+        let tests = [
+            (token::TokenType::Label, "'outer"),
+            (token::TokenType::Colon, ":"),
+            (token::TokenType::Char, "a"),
+            (token::TokenType::Label, "'a"),
+            (token::TokenType::Label, "'é"),
+        ];
+
+        let tokens: Vec<token::Token> = Lexer::new(input).collect();
+        assert_eq!(tokens.len(), tests.len());
+        for (tok, (token_type, literal)) in tokens.iter().zip(tests) {
+            assert_eq!(tok.token_type, token_type);
+            assert_eq!(tok.literal, literal);
+        }
+        assert_eq!((tokens[0].span.start, tokens[0].span.end), (0, 6));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// one\nfn /* two /* three */ */ main // four";
+        let mut l = Lexer::new(input);
+
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::Function);
+        assert_eq!(
+            tok.leading_trivia,
+            vec![token::Trivia {
+                kind: token::TriviaKind::LineComment,
+                text: "// one".to_string(),
+                span: token::Span::new(0, 6, 1, 1),
+            }]
+        );
+
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::Ident);
+        assert_eq!(tok.literal, "main");
+        assert_eq!(
+            tok.leading_trivia,
+            vec![token::Trivia {
+                kind: token::TriviaKind::BlockComment,
+                text: "/* two /* three */ */".to_string(),
+                span: token::Span::new(10, 31, 2, 4),
+            }]
+        );
+
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::EoF);
+        assert_eq!(tok.leading_trivia[0].text, "// four");
+        assert!(l.errors.is_empty());
+
+        let mut l = Lexer::new("1 /* a /* b */");
+        assert_eq!(l.next_token().token_type, token::TokenType::Int);
+        let tok = l.next_token();
+        assert_eq!(tok.token_type, token::TokenType::EoF);
+        assert_eq!(tok.leading_trivia[0].text, "/* a /* b */");
+        assert_eq!(l.errors.len(), 1);
+        assert_eq!(l.errors[0].code, "E0002");
+        assert_eq!(
+            l.errors[0].primary.as_ref().unwrap().span,
+            token::Span::new(2, 4, 1, 3)
+        );
+    }
+}
+
 // #[cfg(test)]
 // mod lexer_tests {
 //     use super::*;
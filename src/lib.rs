@@ -0,0 +1,802 @@
+use std::path::Path;
+use std::{fs, io};
+
+pub mod ast;
+pub mod build;
+pub mod compiler;
+pub mod diagnostic;
+pub mod environment;
+pub mod json;
+pub mod lexer;
+pub mod parser;
+pub mod render;
+pub mod resolver;
+pub mod runtime;
+pub mod source;
+pub mod token;
+
+pub type Diagnostics = Vec<diagnostic::Diagnostic>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArtifactKind {
+    #[default]
+    Asm,
+    Object,
+    Executable,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub kind: ArtifactKind,
+}
+
+/// The output of `compile`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Artifact {
+    Asm(String),
+    Object(Vec<u8>),
+    Executable(Vec<u8>),
+}
+
+impl Artifact {
+    /// Writes the artifact to `path`, marking executables as executable.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Artifact::Asm(asm) => fs::write(path, asm),
+            Artifact::Object(bytes) => fs::write(path, bytes),
+            Artifact::Executable(bytes) => {
+                use std::os::unix::fs::PermissionsExt;
+
+                fs::write(path, bytes)?;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            }
+        }
+    }
+}
+
+/// Parses a single source file.
+pub fn parse(input: &str) -> Result<ast::Program, Diagnostics> {
+    let l = lexer::Lexer::new(input);
+    let mut p = parser::Parser::new(l);
+    let program = p.parse_program();
+
+    if p.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(p.errors)
+    }
+}
+
+/// Compiles a parsed program into the artifact requested by `options`.
+pub fn compile(program: &ast::Program, options: &Options) -> Result<Artifact, Diagnostics> {
+    let to_diagnostics = |errors: Vec<compiler::CompileError>| {
+        errors
+            .iter()
+            .map(|err| err.to_diagnostic())
+            .collect::<Vec<_>>()
+    };
+
+    resolver::Resolver::new()
+        .resolve_program(program)
+        .map_err(to_diagnostics)?;
+    let asm = compiler::Compiler::new()
+        .compile_program(program)
+        .map_err(to_diagnostics)?;
+
+    match options.kind {
+        ArtifactKind::Asm => Ok(Artifact::Asm(asm)),
+        ArtifactKind::Object => build_bytes(&asm, "main.o", build::assemble).map(Artifact::Object),
+        ArtifactKind::Executable => {
+            build_bytes(&asm, "main", build::link).map(Artifact::Executable)
+        }
+    }
+}
+
+/// Runs `build` into a temporary directory and reads back what it wrote.
+fn build_bytes(
+    asm: &str,
+    name: &str,
+    run_cc: fn(&str, &Path) -> Result<(), Box<diagnostic::Diagnostic>>,
+) -> Result<Vec<u8>, Diagnostics> {
+    let dir = build::TempDir::new().map_err(|err| {
+        vec![diagnostic::Diagnostic::error(
+            "E0301",
+            format!("couldn't create a temporary directory: {}", err),
+        )]
+    })?;
+
+    let path = dir.path().join(name);
+    run_cc(asm, &path).map_err(|d| vec![*d])?;
+    fs::read(&path).map_err(|err| {
+        vec![diagnostic::Diagnostic::error(
+            "E0301",
+            format!("couldn't read `{}`: {}", path.display(), err),
+        )]
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::{Command, Output};
+
+    extern crate rand;
+    use rand::Rng;
+
+    use super::*;
+
+    fn execute(program: &str) -> Output {
+        let options = Options {
+            kind: ArtifactKind::Executable,
+        };
+        let artifact = match parse(program).and_then(|program| compile(&program, &options)) {
+            Ok(artifact) => artifact,
+            Err(diagnostics) => panic!("failed to compile: {:?}", diagnostics),
+        };
+
+        let mut rng = rand::thread_rng();
+        fs::create_dir_all("./tmp").expect("failed to create tmp dir");
+        let exe_path = format!("./tmp/{}.out", rng.gen::<u32>());
+        artifact.write(Path::new(&exe_path)).unwrap();
+
+        let output = Command::new(&exe_path).output().expect("failed to execute");
+
+        fs::remove_file(exe_path).expect("failed to remove exe file");
+
+        output
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("fn main() { return 1; }").unwrap().functions.len(), 1);
+
+        let errors = parse("fn main() { return 1 +; }").unwrap_err();
+        assert_eq!(errors[0].code, "E0101");
+    }
+
+    #[test]
+    fn test_artifacts() {
+        let program = parse("fn main() { return 0; }").unwrap();
+
+        match compile(&program, &Options::default()).unwrap() {
+            Artifact::Asm(asm) => assert!(asm.contains("main:")),
+            artifact => panic!("expected assembly, got {:?}", artifact),
+        }
+
+        let options = Options {
+            kind: ArtifactKind::Object,
+        };
+        match compile(&program, &options).unwrap() {
+            Artifact::Object(bytes) => assert!(bytes.starts_with(b"\x7fELF")),
+            artifact => panic!("expected an object file, got {:?}", artifact),
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let program = "fn main() { return 0; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+
+        let program = "fn main() { return 42; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 42);
+
+        let program = "fn main() { return 123; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 123);
+    }
+
+    #[test]
+    fn test_comments() {
+        let program = "
+        // Halves its argument.
+        fn half(a) { return a / /* not a comment end */ 2; }
+        fn main() { /* outer /* inner */ */ return half(6); }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_plusminus() {
+        let program = "fn main() { return 1+2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 3);
+
+        let program = "fn main() { return 1-2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 255);
+
+        let program = "fn main() { return 114+41-136; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 19);
+    }
+
+    #[test]
+    fn test_muldiv() {
+        let program = "fn main() { return 4*2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 8);
+
+        let program = "fn main() { return 4/2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 2);
+
+        let program = "fn main() { return 3*4/2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let program = "fn main() { return 4+2*3; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 10);
+
+        let program = "fn main() { return (4+2)*3; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 18);
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        let program = "fn main() { return 0xF0 + 0b1010 + 0o7 - 1_0; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 247);
+
+        let program = "fn main() { return 0x1_0000_0005 - 0x1_0000_0000; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+
+        // i64::MIN / 2^56 == -128
+        let program = "fn main() { return -9223372036854775808 / 72057594037927936; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 128);
+    }
+
+    #[test]
+    fn test_strings() {
+        let program = r#"fn main() { print("hello, \"world\"\n"); return print("\x41\tb\\\n"); }"#;
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "hello, \"world\"\nA\tb\\\n"
+        );
+
+        let program = r#"fn print(s) { return 7; } fn main() { return print("unused"); }"#;
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_chars() {
+        let program = r"fn main() { return 'a' - 'A' + '\n' + '\u{7F}' - '\x7f'; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 42);
+
+        let program = r"fn main() { return '😀' / 256 - '\u{1F600}' / 256 + 'é'; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 233);
+    }
+
+    #[test]
+    fn test_booleans() {
+        let tests = [
+            ("true", 1),
+            ("false", 0),
+            ("!true", 0),
+            ("!false", 1),
+            ("!0", 1),
+            ("!42", 0),
+            ("!!42", 1),
+            ("(1 < 2) == true", 1),
+            ("!(1 > 2) == !false", 1),
+            ("true + true", 2),
+        ];
+        for (expression, expected) in tests {
+            let program = format!("fn main() {{ return {}; }}", expression);
+            let output = execute(&program);
+            assert_eq!(output.status.code().unwrap(), expected, "{}", expression);
+        }
+
+        let program = "fn main() { let mut a = 0; if (!false) { a = 5; } return a; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_logical() {
+        let tests = [
+            ("1 && 2", 1),
+            ("1 && 0", 0),
+            ("0 && 1", 0),
+            ("0 || 0", 0),
+            ("0 || 7", 1),
+            ("5 || 0", 1),
+            ("1 < 2 && 2 < 3", 1),
+            ("0 || 1 && 0", 0),
+            ("1 || 1 && 0", 1),
+            ("!(0 || 0) && true", 1),
+        ];
+        for (expression, expected) in tests {
+            let program = format!("fn main() {{ return {}; }}", expression);
+            let output = execute(&program);
+            assert_eq!(output.status.code().unwrap(), expected, "{}", expression);
+        }
+
+        // The right operand is skipped when the left one decides the result.
+        let program = r#"
+            fn f(s) { print(s); return 1; }
+            fn main() {
+                let a = 0 && f("a");
+                let b = 1 || f("b");
+                let c = 1 && f("c");
+                let d = 0 || f("d");
+                if (a || b && c) { if (d) { return 3; } }
+                return 4;
+            }
+        "#;
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 3);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "cd");
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let tests = [
+            ("17 % 5", 2),
+            ("-17 % 5 + 10", 8),
+            ("12 & 10", 8),
+            ("12 | 10", 14),
+            ("12 ^ 10", 6),
+            ("1 << 4", 16),
+            ("(-64 >> 2) + 30", 14),
+            ("-64 >> 2 + 30", 255),
+            ("~0 + 2", 1),
+            ("~5 & 255", 250),
+            ("1 + 2 << 3", 24),
+            ("6 & 3 == 3", 0),
+            ("1 | 6 ^ 3 & 5", 7),
+            ("(1 << 62) >> 60", 4),
+        ];
+        for (expression, expected) in tests {
+            let program = format!("fn main() {{ return {}; }}", expression);
+            let output = execute(&program);
+            assert_eq!(output.status.code().unwrap(), expected, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let program = "fn main() {
+            let mut a = 0; let mut i = 0;
+            while (i < 10) { i += 1; a += i; }
+            a -= 5; a *= 2; a /= 3; a %= 20;
+            let mut b = 6; b &= 3; b |= 8; b ^= 1; b <<= 2; b >>= 1;
+            return a * 100 + b;
+        }";
+        let output = execute(program);
+        // a: 55 -> 50 -> 100 -> 33 -> 13, b: 6 -> 2 -> 10 -> 11 -> 44 -> 22.
+        assert_eq!(output.status.code().unwrap(), (13 * 100 + 22) % 256);
+
+        let program = "fn main() { let mut a = 1; return (a += 2) * a; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 9);
+
+        let errors = compile(
+            &parse("fn main() { x += 1; return 0; }").unwrap(),
+            &Options::default(),
+        )
+        .unwrap_err();
+        assert_eq!(errors[0].code, "E0207");
+        assert_eq!(
+            errors[0].message,
+            "cannot assign to undeclared variable `x`"
+        );
+    }
+
+    #[test]
+    fn test_chained_assignment() {
+        let program = "fn main() { let mut a = 0; let mut b = 0; let mut c = 0; a = b = c = 3; b += c *= 2; return a * 100 + b * 10 + c; }";
+        let output = execute(program);
+        // c = 6, b = 9, a = 3.
+        assert_eq!(output.status.code().unwrap(), (300 + 90 + 6) % 256);
+
+        let program = "fn main() { let mut a = 1; return (a = 7) + a; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 14);
+    }
+
+    #[test]
+    fn test_function_names() {
+        // Names that are also registers or assembler keywords must still
+        // name the function.
+        let program = "
+        fn r8() { 1 }
+        fn rax(rsp) { rsp * 2 }
+        fn offset() { 4 }
+        fn main() { r8() + rax(1) + offset() }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_call_arguments() {
+        // Later arguments must not overwrite registers already holding earlier ones.
+        let program = "fn sub(a, b) { return a - b; } \
+                       fn id(x) { return x; } \
+                       fn main() { return sub(1 << 5, 7 % 4) + sub(id(9), id(4)) + sub(40 / 4, 1 << 1); }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 29 + 5 + 8);
+    }
+
+    #[test]
+    fn test_minusprefix() {
+        let program = "fn main() { return -1; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 255);
+
+        let program = "fn main() { return -5+10; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_cmp() {
+        let program = "fn main() { return 1>2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+
+        let program = "fn main() { return 1<2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 1);
+
+        let program = "fn main() { return 1<=2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 1);
+
+        let program = "fn main() { return 1>=2; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+
+        let program = "fn main() { return 1>=1; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 1);
+
+        let program = "fn main() { return 1==1; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 1);
+
+        let program = "fn main() { return 1!=1; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_semicolon() {
+        let program = "fn main() { 5 + 10; 8 * 5 }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 40);
+
+        // With a `;` the final expression is a statement, and `main` has no value.
+        let program = "fn main() { 5 + 10; 8 * 5; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_ident() {
+        let program = "fn main() { let a = 10; a + 10 }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 20);
+    }
+
+    #[test]
+    fn test_complex_program() {
+        let program = "fn main() { let a = 5; let b = 10; let c = a + b; let d = a * b; d - c }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 35);
+    }
+
+    #[test]
+    fn test_return() {
+        let program = "fn main() { let a = 5; return a + 2; a + 5; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_if_else() {
+        let program = "fn main() { return if (5 > 4) { 7 } else { 8 }; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+
+        let program = "fn main() { return if (5 < 4) { 7 } else { 8 }; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_while() {
+        let program = "fn main() { let mut a = 0; while(a < 10) { a = a + 1; } return a; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_else_if() {
+        let program = "
+        fn classify(n) {
+            if (n < 0) { 1 } else if (n == 0) { 2 } else if (n < 10) { 3 } else { 4 }
+        }
+        fn main() { classify(0 - 5) * 1000 + classify(0) * 100 + classify(7) * 10 + classify(42) }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 1234 % 256);
+
+        let program = "fn main() { let mut a = 0; if (a) { a = 1; } else if (1) { a = 2; } a }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let program = "
+        fn main() {
+            let mut i = 0;
+            let mut sum = 0;
+            while (1) {
+                i += 1;
+                if (i > 9) { break; }
+                if (i % 2 == 0) { continue; }
+                sum += i;
+            }
+            sum
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 25);
+
+        let program = "
+        fn main() {
+            let mut count = 0;
+            let mut i = 0;
+            'outer: while (i < 10) {
+                i += 1;
+                let mut j = 0;
+                while (1) {
+                    j += 1;
+                    if (j > i) { continue 'outer; }
+                    if (i * j > 20) { break 'outer; }
+                    count += 1;
+                }
+            }
+            count
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 14);
+    }
+
+    #[test]
+    fn test_loop_control_unwinds_stack() {
+        // Each iteration jumps out of the middle of expressions that have
+        // already pushed temporaries. If they were left behind, a million
+        // iterations would overflow the stack.
+        let program = "
+        fn add(a, b) { a + b }
+        fn main() {
+            let mut i = 0;
+            let mut sum = 0;
+            while (i < 1000000) {
+                i += 1;
+                sum += add(i, { if (i % 2) { continue; } 0 })
+                    - { while (1) { let y = 3 + { break; }; } i } + 1;
+            }
+            sum
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 500000 % 256);
+    }
+
+    #[test]
+    fn test_block_expressions() {
+        let program = "
+        fn add(a, b) { a + b }
+        fn main() {
+            let x = { let y = 3; y * y } + 1;
+            let z = add({ 5 }, if (x > 5) { { x } } else { 0 });
+            let unit = { let w = 1; w; };
+            { x + 1 } - 1;
+            z + unit + if (1) { 100; }
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 15);
+
+        // The value of a loop or a block with no tail expression is 0, and
+        // neither leaves anything behind on the stack.
+        let program = "
+        fn main() {
+            let mut i = 0;
+            let mut sum = 0;
+            while (i < 1000) { i += 1; sum += { let j = i % 2; j } + while (0) {}; }
+            sum - 500
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_if_without_else() {
+        // Whether or not its block runs, an `if` without `else` is 0.
+        let program = "
+        fn f(n) { let mut a = 0; let v = if (n) { a = 7; }; v * 100 + a }
+        fn main() { f(1) + f(0) }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+
+        for program in [
+            "fn f(n) { if (n) { 5 } } fn main() { f(1) }",
+            "fn main() { let v = if (1) { 5 }; v }",
+            "fn main() { if (1) { 5 }; 0 }",
+        ] {
+            let errors = compile(&parse(program).unwrap(), &Options::default()).unwrap_err();
+            assert_eq!(errors[0].code, "E0214", "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_conditional_return_in_block_expression() {
+        // A `return` that `||` or `&&` may skip must not cut off the code
+        // after it.
+        let program = "fn g(n) { let x = n || { return 1; }; x + 5 } fn main() { g(1) }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 6);
+
+        let program = "fn g(n) { let x = n && { return 9; }; x + 5 } fn main() { g(0) }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+
+        let program = "fn g(n) { let x = n && { return 9; }; x + 5 } fn main() { g(1) }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 9);
+    }
+
+    #[test]
+    fn test_block() {
+        let program = "fn main() { let a = 5; { let b = 2; } return a + b; }";
+        let errors = compile(&parse(program).unwrap(), &Options::default()).unwrap_err();
+        assert_eq!(errors[0].code, "E0201");
+    }
+
+    #[test]
+    fn test_let() {
+        // Shadowing: an inner `let` hides the outer variable until its block
+        // ends, and a `let` in the same scope may refer to what it shadows.
+        let program = "fn main() {
+            let x = 2;
+            let mut y = 0;
+            { let x = x * 10; y = x; }
+            let x = x + 1;
+            { let mut y = 100; y += 1; }
+            return x * 100 + y;
+        }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), (3 * 100 + 20) % 256);
+
+        let program =
+            "fn main() { let mut a = 0; while (a < 3) { let b = a + 1; a = b; } return a; }";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 3);
+
+        let errors = |program: &str| -> Vec<(&'static str, String)> {
+            compile(&parse(program).unwrap(), &Options::default())
+                .unwrap_err()
+                .into_iter()
+                .map(|d| (d.code, d.message))
+                .collect()
+        };
+        assert_eq!(
+            errors("fn main() { let a = 1; a = 2; return a; }"),
+            vec![(
+                "E0208",
+                "cannot assign twice to immutable variable `a`".to_string()
+            )]
+        );
+        assert_eq!(
+            errors("fn main() { let mut a = 1; { let a = 2; a += 1; } return a; }"),
+            vec![(
+                "E0208",
+                "cannot assign twice to immutable variable `a`".to_string()
+            )]
+        );
+        assert_eq!(
+            errors("fn f(n) { n = 1; return n; } fn main() { return f(0); }"),
+            vec![(
+                "E0208",
+                "cannot assign twice to immutable variable `n`".to_string()
+            )]
+        );
+        assert_eq!(
+            errors("fn main() { a = 1; return 0; }"),
+            vec![(
+                "E0207",
+                "cannot assign to undeclared variable `a`".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_fn() {
+        let program = "
+        fn add(a, b) {
+            return a + b;
+        }
+        fn main() {
+            add(1, 2)
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_fibonacci() {
+        let program = "
+        fn fib(a, b, i) {
+            if(i >= 10) { return b; }
+
+            fib(b, a + b, i + 1)
+        }
+        fn main() {
+            fib(1, 1, 0)
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 144);
+    }
+
+    #[test]
+    fn test_fn2() {
+        let program = "
+        fn f(a, i) {
+            if(i >= 2) { return a; }
+
+            f(a + 2, i + 1)
+        }
+        fn main() {
+            f(1, 0)
+        }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_implicit_return() {
+        let program = "
+        fn sign(n) { if (n < 0) { return 0 - 1; } else { return 1; } }
+        fn abs(n) { n * sign(n) }
+        fn count(n) { let mut i = 0; while (i < n) { i += 1; } }
+        fn main() { count(3) + abs(0 - 7) }
+        ";
+        let output = execute(program);
+        assert_eq!(output.status.code().unwrap(), 7);
+
+        let program = "fn f(n) { if (n > 0) { return 1; } n; } fn main() { f(1) }";
+        let errors = compile(&parse(program).unwrap(), &Options::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "E0211");
+        assert_eq!(
+            errors[0].message,
+            "function `f` returns a value on some paths but not all"
+        );
+    }
+}
@@ -1,271 +1,202 @@
-use std::env;
-mod ast;
-mod compiler;
-mod environment;
-mod lexer;
-mod parser;
-mod token;
+use std::{env, process};
+mod driver;
 
 // c-compiler
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    if args.len() != 2 {
-        panic!("Incorrect number of arguments!");
-    }
-
-    let l = lexer::Lexer::new(&args[1]);
-    let mut p = parser::Parser::new(l);
-    let program = p.parse_program();
+    let options = match driver::Options::parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, driver::USAGE);
+            process::exit(2);
+        }
+    };
 
-    let asm = compiler::Compiler::new().compile_program(program).unwrap();
+    if options.help {
+        println!("{}", driver::USAGE);
+        return;
+    }
 
-    println!("{}", asm);
+    process::exit(driver::run(&options));
 }
 
 #[cfg(test)]
 mod test {
     use std::fs;
     use std::io::Write;
-    use std::process::Output;
-    use std::{fs::File, process::Command};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
 
     extern crate rand;
     use rand::Rng;
 
     use super::*;
 
-    fn execute(program: &str) -> Output {
-        let mut rng = rand::thread_rng();
-        execute_with_filename(program, &rng.gen::<u32>().to_string())
-    }
-
-    fn execute_with_filename(program: &str, filename: &str) -> Output {
-        let asm_path = String::new() + "./tmp/" + filename + ".s";
-        let exe_path = String::new() + "./tmp/" + filename + ".out";
-
+    #[test]
+    fn test_syntax_error() {
         let output = Command::new(env!("CARGO"))
-            .args(vec!["run", "--", program])
+            .args(vec!["run", "--", "-e", "fn main() { return 1 +; }"])
             .output()
             .expect("failed to compile");
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-        if !output.status.success() {
-            return output;
-        }
-
-        let mut path = env::current_dir().unwrap();
-        path.push(&asm_path);
-        let mut file = File::create(path).unwrap();
-        write!(file, "{}", String::from_utf8_lossy(&output.stdout)).unwrap();
-        file.flush().unwrap();
-
-        Command::new("cc")
-            .args([&asm_path, "-o", &exe_path])
-            .output()
-            .expect("failed to build");
-
-        let output = Command::new(&exe_path).output().expect("failed to execute");
-
-        fs::remove_file(asm_path).expect("failed to remove asm file");
-        fs::remove_file(exe_path).expect("failed to remove exe file");
-
-        output
+        assert_eq!(output.status.code().unwrap(), 1);
+        assert!(output.stdout.is_empty());
+        assert!(stderr.contains("error[E0101]: expected expression, found `;`"));
+        assert!(stderr.contains("1 | fn main() { return 1 +; }"));
+        assert!(stderr.contains("  |                       ^ expected expression"));
     }
 
     #[test]
-    fn test_compile() {
-        let program = "fn main() { return 0; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 0);
-
-        let program = "fn main() { return 42; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 42);
+    fn test_inputs() {
+        let mut rng = rand::thread_rng();
+        let mut path = env::current_dir().unwrap();
+        fs::create_dir_all(path.join("tmp")).expect("failed to create tmp dir");
+        path.push(format!("tmp/{}.src", rng.gen::<u32>()));
+        fs::write(&path, "fn add(a, b) {\n    return a + b;\n}\n").unwrap();
+
+        let mut child = Command::new(env!("CARGO"))
+            .args(vec!["run", "--"])
+            .arg(&path)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to compile");
+        write!(
+            child.stdin.take().unwrap(),
+            "fn main() {{\n    return add(1, x);\n}}\n"
+        )
+        .unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-        let program = "fn main() { return 123; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 123);
-    }
+        fs::remove_file(&path).expect("failed to remove source file");
 
-    #[test]
-    fn test_plusminus() {
-        let program = "fn main() { return 1+2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 3);
+        assert_eq!(output.status.code().unwrap(), 1);
+        assert!(stderr.contains("--> <stdin>:2:19"));
+        assert!(stderr.contains("2 |     return add(1, x);"));
 
-        let program = "fn main() { return 1-2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 255);
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "no_such_file.src"])
+            .output()
+            .expect("failed to compile");
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-        let program = "fn main() { return 114+41-136; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 19);
+        assert_eq!(output.status.code().unwrap(), 1);
+        assert!(stderr.contains("error[E0300]: couldn't read `no_such_file.src`"));
     }
 
     #[test]
-    fn test_muldiv() {
-        let program = "fn main() { return 4*2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 8);
+    fn test_build() {
+        let mut rng = rand::thread_rng();
+        fs::create_dir_all("./tmp").expect("failed to create tmp dir");
+        let asm_path = format!("./tmp/{}.s", rng.gen::<u32>());
 
-        let program = "fn main() { return 4/2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 2);
+        let output = Command::new(env!("CARGO"))
+            .args(vec![
+                "run",
+                "--",
+                "-e",
+                "fn main() { return 7; }",
+                "-o",
+                &asm_path,
+            ])
+            .output()
+            .expect("failed to compile");
+        let asm = fs::read_to_string(&asm_path).unwrap();
+        fs::remove_file(&asm_path).expect("failed to remove asm file");
 
-        let program = "fn main() { return 3*4/2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 6);
-    }
+        assert_eq!(output.status.code().unwrap(), 0);
+        assert!(output.stdout.is_empty());
+        assert!(asm.contains("main:"));
 
-    #[test]
-    fn test_arithmetic() {
-        let program = "fn main() { return 4+2*3; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 10);
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "build", "-e", "fn main() { return 7; }"])
+            .args(vec!["-o", "./tmp/never_written.out"])
+            .env("CC", "no-such-cc")
+            .output()
+            .expect("failed to compile");
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-        let program = "fn main() { return (4+2)*3; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 18);
+        assert_eq!(output.status.code().unwrap(), 1);
+        assert!(stderr.contains("error[E0302]: couldn't run `no-such-cc`"));
+        assert!(!Path::new("./tmp/never_written.out").exists());
     }
 
     #[test]
-    fn test_minusprefix() {
-        let program = "fn main() { return -1; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 255);
-
-        let program = "fn main() { return -5+10; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 5);
-    }
+    fn test_emit() {
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "-e", "fn main() { return 1 + 2; }"])
+            .args(vec!["--emit=tokens,ast-json"])
+            .output()
+            .expect("failed to compile");
+        let stdout = String::from_utf8_lossy(&output.stdout);
 
-    #[test]
-    fn test_cmp() {
-        let program = "fn main() { return 1>2; }";
-        let output = execute(program);
         assert_eq!(output.status.code().unwrap(), 0);
+        assert!(stdout.starts_with("<input>:1:1\tFunction\t\"fn\"\n"));
+        assert!(stdout.contains("<input>:1:22\tPlus\t\"+\"\n"));
+        assert!(stdout.contains(r#"{"functions":[{"kind":"Function","name":"main","#));
+        assert!(!stdout.contains("main:"));
 
-        let program = "fn main() { return 1<2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 1);
-
-        let program = "fn main() { return 1<=2; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 1);
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "--emit=ast", "-e"])
+            .arg("fn main() { let x = { 1 }; if (x) { 2 } else { 3 } }")
+            .output()
+            .expect("failed to compile");
+        let stdout = String::from_utf8_lossy(&output.stdout);
 
-        let program = "fn main() { return 1>=2; }";
-        let output = execute(program);
+        // Tail expressions are printed without `;`, so the output is the
+        // same program.
         assert_eq!(output.status.code().unwrap(), 0);
+        assert_eq!(
+            stdout,
+            "fn main() {\r\n\tlet x = {\r\n\t1\r\n};\r\n\t\
+             if (x) {\r\n\t2\r\n} else {\r\n\t3\r\n}\r\n}\n"
+        );
 
-        let program = "fn main() { return 1>=1; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 1);
+        let mut rng = rand::thread_rng();
+        fs::create_dir_all("./tmp").expect("failed to create tmp dir");
+        let obj_path = format!("./tmp/{}.o", rng.gen::<u32>());
 
-        let program = "fn main() { return 1==1; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 1);
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "-e", "fn main() { return 0; }"])
+            .args(vec!["--emit", "obj", "-o", &obj_path])
+            .output()
+            .expect("failed to compile");
+        let obj = fs::read(&obj_path).unwrap();
+        fs::remove_file(&obj_path).expect("failed to remove object file");
 
-        let program = "fn main() { return 1!=1; }";
-        let output = execute(program);
         assert_eq!(output.status.code().unwrap(), 0);
+        assert!(obj.starts_with(b"\x7fELF"));
     }
 
     #[test]
-    fn test_semicolon() {
-        let program = "fn main() { 5 + 10; 8 * 5; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 40);
-    }
-
-    #[test]
-    fn test_ident() {
-        let program = "fn main() { a = 10; a + 10; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 20);
-    }
-
-    #[test]
-    fn test_complex_program() {
-        let program = "fn main() { a = 5; b = 10; c = a + b; d = a * b; d - c; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 35);
-    }
-
-    #[test]
-    fn test_return() {
-        let program = "fn main() { a = 5; return a + 2; a + 5; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 7);
-    }
-
-    #[test]
-    fn test_if_else() {
-        let program = "fn main() { return if (5 > 4) { 7 } else { 8 }; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 7);
-
-        let program = "fn main() { return if (5 < 4) { 7 } else { 8 }; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 8);
-    }
-
-    #[test]
-    fn test_while() {
-        let program = "fn main() { a = 0; while(a < 10) { a = a + 1; } return a; }";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 10);
-    }
-
-    #[test]
-    fn test_block() {
-        let program = "fn main() { a = 5; { b = 2; } return a + b; }";
-        let output = execute(program);
-        assert!(!output.status.success());
-    }
-
-    #[test]
-    fn test_fn() {
-        let program = "
-        fn add(a, b) {
-            return a + b;
-        }
-        fn main() {
-            add(1, 2);
-        }
-        ";
-        let output = execute(program);
+    fn test_run() {
+        let output = Command::new(env!("CARGO"))
+            .args(vec![
+                "run",
+                "--",
+                "run",
+                "-e",
+                "fn main() { return 3; }",
+                "--",
+                "x",
+            ])
+            .output()
+            .expect("failed to compile");
         assert_eq!(output.status.code().unwrap(), 3);
-    }
 
-    #[test]
-    fn test_fibonacci() {
-        let program = "
-        fn fib(a, b, i) {
-            if(i >= 10) { return b; }
-
-            fib(b, a + b, i + 1);
-        }
-        fn main() {
-            fib(1, 1, 0);
-        }
-        ";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 144);
-    }
-
-    #[test]
-    fn test_fn2() {
-        let program = "
-        fn f(a, i) {
-            if(i >= 2) { return a; }
+        let program = "fn main() { let mut a = 0; while(1) { a = a + 1; } return a; }";
+        let output = Command::new(env!("CARGO"))
+            .args(vec!["run", "--", "run", "--timeout", "0.2", "-e", program])
+            .output()
+            .expect("failed to compile");
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-            f(a + 2, i + 1);
-        }
-        fn main() {
-            f(1, 0);
-        }
-        ";
-        let output = execute(program);
-        assert_eq!(output.status.code().unwrap(), 5);
+        assert_eq!(output.status.code().unwrap(), 124);
+        assert!(stderr.contains("error[E0305]: program timed out after 200ms"));
     }
 }
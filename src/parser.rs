@@ -1,14 +1,20 @@
 use std::rc::Rc;
 
-use super::{ast, lexer, token};
+use super::{ast, diagnostic, lexer, token};
 
 #[allow(dead_code)]
 #[derive(PartialEq, PartialOrd, Debug)]
 pub enum Precedence {
     Lowest,
     Assign,
+    LogicalOr,
+    LogicalAnd,
+    BitOr,
+    BitXor,
+    BitAnd,
     Equals,
     LessGreater,
+    Shift,
     Sum,
     Product,
     Prefix,
@@ -20,17 +26,35 @@ pub enum Precedence {
 #[allow(dead_code)]
 pub fn token_type_to_precedence(t: &token::TokenType) -> Precedence {
     match t {
-        token::TokenType::Assign => Precedence::Assign,
+        token::TokenType::Assign
+        | token::TokenType::PlusAssign
+        | token::TokenType::MinusAssign
+        | token::TokenType::AsteriskAssign
+        | token::TokenType::SlashAssign
+        | token::TokenType::PercentAssign
+        | token::TokenType::AmpersandAssign
+        | token::TokenType::PipeAssign
+        | token::TokenType::CaretAssign
+        | token::TokenType::ShlAssign
+        | token::TokenType::ShrAssign => Precedence::Assign,
+        token::TokenType::Or => Precedence::LogicalOr,
+        token::TokenType::And => Precedence::LogicalAnd,
+        token::TokenType::Pipe => Precedence::BitOr,
+        token::TokenType::Caret => Precedence::BitXor,
+        token::TokenType::Ampersand => Precedence::BitAnd,
         token::TokenType::Eq => Precedence::Equals,
         token::TokenType::NotEq => Precedence::Equals,
         token::TokenType::LtEq => Precedence::LessGreater,
         token::TokenType::GtEq => Precedence::LessGreater,
         token::TokenType::Lt => Precedence::LessGreater,
         token::TokenType::Gt => Precedence::LessGreater,
+        token::TokenType::Shl => Precedence::Shift,
+        token::TokenType::Shr => Precedence::Shift,
         token::TokenType::Plus => Precedence::Sum,
         token::TokenType::Minus => Precedence::Sum,
         token::TokenType::Slash => Precedence::Product,
         token::TokenType::Asterisk => Precedence::Product,
+        token::TokenType::Percent => Precedence::Product,
         // token::TokenType::DOT => Precedence::DOT,
         token::TokenType::LParen => Precedence::Call,
         // token::TokenType::LBRACKET => Precedence::INDEX,
@@ -43,7 +67,7 @@ pub struct Parser {
     l: lexer::Lexer,
     cur_token: Rc<token::Token>,
     peek_token: Rc<token::Token>,
-    pub errors: Vec<String>,
+    pub errors: Vec<diagnostic::Diagnostic>,
 }
 
 #[allow(dead_code)]
@@ -51,14 +75,16 @@ impl Parser {
     pub fn new(l: lexer::Lexer) -> Parser {
         let mut p = Parser {
             l,
-            cur_token: Rc::new(token::Token {
-                token_type: token::TokenType::Illegal,
-                literal: "".to_string(),
-            }),
-            peek_token: Rc::new(token::Token {
-                token_type: token::TokenType::Illegal,
-                literal: "".to_string(),
-            }),
+            cur_token: Rc::new(token::new_token(
+                token::TokenType::Illegal,
+                "".to_string(),
+                token::Span::default(),
+            )),
+            peek_token: Rc::new(token::new_token(
+                token::TokenType::Illegal,
+                "".to_string(),
+                token::Span::default(),
+            )),
             errors: Vec::new(),
         };
 
@@ -69,41 +95,98 @@ impl Parser {
     }
 
     fn peek_error(&mut self, t: token::TokenType) {
-        self.errors.push(format!(
-            "\r\nexpected next token to be {:?}, got {:?} instead.",
-            t, self.peek_token.token_type
-        ))
+        if self.peek_token_is(&token::TokenType::Illegal) {
+            return;
+        }
+
+        self.error(
+            diagnostic::Diagnostic::error(
+                "E0100",
+                format!("expected {}, found {}", t, self.peek_token.token_type),
+            )
+            .with_primary(self.peek_token.span, &format!("expected {}", t)),
+        )
     }
 
     fn next_token(&mut self) {
         self.cur_token = Rc::clone(&self.peek_token);
         self.peek_token = Rc::new(self.l.next_token());
+        self.errors.append(&mut self.l.errors);
     }
 
     pub fn parse_program(&mut self) -> ast::Program {
         let mut program = ast::Program {
             functions: Vec::new(),
         };
-        while self.cur_token.token_type == token::TokenType::Function {
-            if let Some(func) = self.parse_function() {
-                program.functions.push(func);
+        while !self.cur_token_is(&token::TokenType::EoF) {
+            if !self.cur_token_is(&token::TokenType::Function) {
+                if !self.cur_token_is(&token::TokenType::Illegal) {
+                    self.error(
+                        diagnostic::Diagnostic::error(
+                            "E0103",
+                            format!("expected `fn`, found {}", self.cur_token.token_type),
+                        )
+                        .with_primary(self.cur_token.span, "expected a function definition"),
+                    );
+                }
+                self.synchronize_function();
+                continue;
+            }
+
+            let start = self.cur_token.span;
+            match self.parse_function() {
+                Some(func) => {
+                    program.functions.push(func);
+                    self.next_token();
+                }
+                None => {
+                    if self.cur_token.span == start {
+                        self.next_token();
+                    }
+                    self.synchronize_function();
+                }
             }
-            self.next_token();
         }
 
         program
     }
 
+    /// Skips tokens until the start of the next function definition.
+    fn synchronize_function(&mut self) {
+        while !self.cur_token_is(&token::TokenType::Function)
+            && !self.cur_token_is(&token::TokenType::EoF)
+        {
+            self.next_token();
+        }
+    }
+
+    /// Skips the rest of a malformed statement, stopping after the next `;`
+    /// or at a `}` or `fn` that may close the enclosing block or function.
+    fn synchronize_statement(&mut self) {
+        while !self.cur_token_is(&token::TokenType::EoF) {
+            match self.cur_token.token_type {
+                token::TokenType::SemiColon => {
+                    self.next_token();
+                    return;
+                }
+                token::TokenType::RBrace | token::TokenType::Function => return,
+                _ => self.next_token(),
+            }
+        }
+    }
+
     fn parse_function(&mut self) -> Option<ast::Function> {
         let mut function = ast::Function {
             name: "".to_string(),
             parameters: Vec::new(),
             body: ast::Statement::Block {
                 statements: Vec::new(),
+                span: self.cur_token.span,
             },
+            span: self.cur_token.span,
         };
 
-        if !self.expect_peek(token::TokenType::Ident) {
+        if !self.expect_ident() {
             return None;
         }
         function.name = self.cur_token.literal.to_string();
@@ -119,6 +202,7 @@ impl Parser {
         }
 
         function.body = self.parse_block_statement()?;
+        function.span = function.span.to(function.body.span());
 
         Some(function)
     }
@@ -130,13 +214,17 @@ impl Parser {
             self.next_token();
             return Some(identifiers);
         }
-        self.next_token();
 
+        if !self.expect_ident() {
+            return None;
+        }
         identifiers.push(self.cur_token.literal.to_string());
 
         while self.peek_token_is(&token::TokenType::Comma) {
             self.next_token();
-            self.next_token();
+            if !self.expect_ident() {
+                return None;
+            }
             identifiers.push(self.cur_token.literal.to_string());
         }
 
@@ -149,45 +237,56 @@ impl Parser {
 
     fn parse_statement(&mut self) -> Option<ast::Statement> {
         match self.cur_token.token_type {
-            // token::TokenType::LET => return self.parse_let_statement(),
-            token::TokenType::LBrace => self.parse_block_statement(),
+            token::TokenType::Let => self.parse_let_statement(),
+            token::TokenType::LBrace => self.parse_block_expression_statement(),
             token::TokenType::Return => self.parse_return_statement(),
+            token::TokenType::Break | token::TokenType::Continue => {
+                Some(self.parse_loop_control_statement())
+            }
             _ => self.parse_expression_statement(),
         }
     }
 
-    // fn parse_let_statement(&mut self) -> Option<ast::Statement> {
-    //     if !self.expect_peek(token::TokenType::IDENT) {
-    //         return None;
-    //     }
-    //     let name = ast::Expression::Identifier {
-    //         value: self.cur_token.literal.clone(),
-    //     };
-    //     if !self.expect_peek(token::TokenType::ASSIGN) {
-    //         return None;
-    //     }
+    fn parse_let_statement(&mut self) -> Option<ast::Statement> {
+        let span = self.cur_token.span;
 
-    //     self.next_token();
+        let mutable = self.peek_token_is(&token::TokenType::Mut);
+        if mutable {
+            self.next_token();
+        }
+        if !self.expect_ident() {
+            return None;
+        }
+        let name = self.cur_token.literal.clone();
+        if !self.expect_peek(token::TokenType::Assign) {
+            return None;
+        }
 
-    //     if let Some(expression) = self.parse_expression(Precedence::LOWEST) {
-    //         let stmt = ast::Statement::Let {
-    //             name,
-    //             value: expression,
-    //         };
-    //         if self.peek_token_is(&token::TokenType::SEMICOLON) {
-    //             self.next_token();
-    //         }
-    //         return Some(stmt);
-    //     } else {
-    //         return None;
-    //     }
-    // }
+        self.next_token();
+
+        if let Some(expression) = self.parse_expression(Precedence::Lowest) {
+            let stmt = ast::Statement::Let {
+                name,
+                mutable,
+                span: span.to(expression.span()),
+                value: expression,
+            };
+            if self.peek_token_is(&token::TokenType::SemiColon) {
+                self.next_token();
+            }
+            Some(stmt)
+        } else {
+            None
+        }
+    }
 
     fn parse_return_statement(&mut self) -> Option<ast::Statement> {
+        let span = self.cur_token.span;
         self.next_token();
 
         if let Some(expression) = self.parse_expression(Precedence::Lowest) {
             let stmt = ast::Statement::Return {
+                span: span.to(expression.span()),
                 return_value: expression,
             };
             if self.peek_token_is(&token::TokenType::SemiColon) {
@@ -199,37 +298,101 @@ impl Parser {
         }
     }
 
+    /// Parses `break` or `continue` with an optional label.
+    fn parse_loop_control_statement(&mut self) -> ast::Statement {
+        let keyword = self.cur_token.token_type.clone();
+        let mut span = self.cur_token.span;
+
+        let label = if self.peek_token_is(&token::TokenType::Label) {
+            self.next_token();
+            span = span.to(self.cur_token.span);
+            Some(self.cur_token.literal.clone())
+        } else {
+            None
+        };
+        if self.peek_token_is(&token::TokenType::SemiColon) {
+            self.next_token();
+        }
+
+        if keyword == token::TokenType::Break {
+            ast::Statement::Break { label, span }
+        } else {
+            ast::Statement::Continue { label, span }
+        }
+    }
+
     fn parse_expression_statement(&mut self) -> Option<ast::Statement> {
         if let Some(expression) = self.parse_expression(Precedence::Lowest) {
-            let stmt = ast::Statement::Expression { expression };
-            if self.peek_token_is(&token::TokenType::SemiColon) {
+            let semicolon = self.peek_token_is(&token::TokenType::SemiColon);
+            if semicolon {
                 self.next_token();
             }
-            Some(stmt)
+            Some(ast::Statement::Expression {
+                span: expression.span(),
+                expression,
+                semicolon,
+            })
         } else {
             None
         }
     }
 
+    /// Parses a block at the start of a statement. Like in Rust, the block
+    /// ends the expression, so `{ a } - 1` is two statements.
+    fn parse_block_expression_statement(&mut self) -> Option<ast::Statement> {
+        let expression = self.parse_block_expression()?;
+
+        let semicolon = self.peek_token_is(&token::TokenType::SemiColon);
+        if semicolon {
+            self.next_token();
+        }
+        Some(ast::Statement::Expression {
+            span: expression.span(),
+            expression,
+            semicolon,
+        })
+    }
+
+    fn parse_block_expression(&mut self) -> Option<ast::Expression> {
+        self.parse_block_statement()
+            .map(|body| ast::Expression::BlockExpression {
+                span: body.span(),
+                body: Box::new(body),
+            })
+    }
+
     fn parse_block_statement(&mut self) -> Option<ast::Statement> {
+        let span = self.cur_token.span;
         let mut statements = Vec::new();
 
         self.next_token();
 
         while !self.cur_token_is(&token::TokenType::RBrace)
             && !self.cur_token_is(&token::TokenType::EoF)
+            && !self.cur_token_is(&token::TokenType::Function)
         {
-            if let Some(stmt) = self.parse_statement() {
-                statements.push(stmt);
+            match self.parse_statement() {
+                Some(stmt) => {
+                    statements.push(stmt);
+                    self.next_token();
+                }
+                None => self.synchronize_statement(),
             }
-            self.next_token();
         }
 
-        if self.cur_token_is(&token::TokenType::EoF) {
+        if !self.cur_token_is(&token::TokenType::RBrace) {
+            self.error(
+                diagnostic::Diagnostic::error("E0104", "unclosed block".to_string())
+                    .with_primary(self.cur_token.span, "expected `}`")
+                    .with_secondary(span, "block opened here"),
+            );
             return None;
         }
 
-        Some(ast::Statement::Block { statements })
+        Some(ast::Statement::Block {
+            statements,
+            span: span.to(self.cur_token.span),
+        })
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Option<ast::Expression> {
@@ -239,17 +402,11 @@ impl Parser {
                 && precedence < self.peek_precedence()
             {
                 self.next_token();
-                if let Some(left_exp_new) = self.parse_infix_expression_fns(left.clone()) {
-                    left = Box::new(left_exp_new);
-                } else {
-                    return Some(*left);
-                }
+                *left = self.parse_infix_expression_fns(left.clone())?;
             }
 
             Some(*left)
         } else {
-            self.no_prefix_parse_fn_error();
-
             None
         }
     }
@@ -258,18 +415,25 @@ impl Parser {
         match self.cur_token.token_type {
             token::TokenType::Ident => Some(self.parse_identifier()),
             token::TokenType::Int => self.parse_integer_literal(),
-            // token::TokenType::STRING => self.parse_string_literal(),
-            // token::TokenType::BANG => self.parse_prefix_expression(),
+            token::TokenType::String => Some(self.parse_string_literal()),
+            token::TokenType::Char => Some(self.parse_char_literal()),
+            token::TokenType::Bang => self.parse_prefix_expression(),
+            token::TokenType::Tilde => self.parse_prefix_expression(),
             token::TokenType::Minus => self.parse_prefix_expression(),
-            // token::TokenType::TRUE => Some(self.parse_boolean()),
-            // token::TokenType::FALSE => Some(self.parse_boolean()),
+            token::TokenType::True => Some(self.parse_boolean()),
+            token::TokenType::False => Some(self.parse_boolean()),
             token::TokenType::LParen => self.parse_grouped_expression(),
             // token::TokenType::LBRACKET => self.parse_array_literal(),
             token::TokenType::If => self.parse_if_expression(),
-            token::TokenType::While => self.parse_while_expression(),
+            token::TokenType::While => self.parse_while_expression(None, self.cur_token.span),
+            token::TokenType::Label => self.parse_labeled_expression(),
             // token::TokenType::FUNCTION => self.parse_function_literal(),
-            // token::TokenType::LBRACE => self.parse_hash_literal(),
-            _ => None,
+            token::TokenType::LBrace => self.parse_block_expression(),
+            _ => {
+                self.no_prefix_parse_fn_error();
+
+                None
+            }
         }
     }
 
@@ -282,8 +446,26 @@ impl Parser {
             token::TokenType::Minus => self.parse_infix_expression(left_exp),
             token::TokenType::Slash => self.parse_infix_expression(left_exp),
             token::TokenType::Asterisk => self.parse_infix_expression(left_exp),
+            token::TokenType::Percent => self.parse_infix_expression(left_exp),
+            token::TokenType::Ampersand => self.parse_infix_expression(left_exp),
+            token::TokenType::Pipe => self.parse_infix_expression(left_exp),
+            token::TokenType::Caret => self.parse_infix_expression(left_exp),
+            token::TokenType::Shl => self.parse_infix_expression(left_exp),
+            token::TokenType::Shr => self.parse_infix_expression(left_exp),
             // token::TokenType::DOT => self.parse_infix_expression(left_exp),
-            token::TokenType::Assign => self.parse_assign_expression(left_exp),
+            token::TokenType::Assign
+            | token::TokenType::PlusAssign
+            | token::TokenType::MinusAssign
+            | token::TokenType::AsteriskAssign
+            | token::TokenType::SlashAssign
+            | token::TokenType::PercentAssign
+            | token::TokenType::AmpersandAssign
+            | token::TokenType::PipeAssign
+            | token::TokenType::CaretAssign
+            | token::TokenType::ShlAssign
+            | token::TokenType::ShrAssign => self.parse_assign_expression(left_exp),
+            token::TokenType::And => self.parse_infix_expression(left_exp),
+            token::TokenType::Or => self.parse_infix_expression(left_exp),
             token::TokenType::Eq => self.parse_infix_expression(left_exp),
             token::TokenType::NotEq => self.parse_infix_expression(left_exp),
             token::TokenType::Lt => self.parse_infix_expression(left_exp),
@@ -313,22 +495,49 @@ impl Parser {
 
     fn parse_prefix_expression(&mut self) -> Option<ast::Expression> {
         let expression_operator = self.cur_token.literal.clone();
+        let span = self.cur_token.span;
 
         self.next_token();
 
-        self.parse_expression(Precedence::Prefix)
-            .map(|right| ast::Expression::PrefixExpression {
-                operator: expression_operator,
-                right: Box::new(right),
-            })
+        // A literal right after a minus sign may be one larger than
+        // `i64::MAX`, so that `i64::MIN` can be written.
+        let right = if expression_operator == "-" && self.cur_token_is(&token::TokenType::Int) {
+            self.parse_integer_literal_with_limit(1 << 63)
+        } else {
+            self.parse_expression(Precedence::Prefix)
+        };
+
+        right.map(|right| ast::Expression::PrefixExpression {
+            operator: expression_operator,
+            span: span.to(right.span()),
+            right: Box::new(right),
+        })
     }
 
     fn parse_assign_expression(&mut self, left: Box<ast::Expression>) -> Option<ast::Expression> {
-        let precedence = self.cur_precedence();
+        let operator = self.cur_token.literal.clone();
+
+        if !matches!(*left, ast::Expression::Identifier { .. }) {
+            self.error(
+                diagnostic::Diagnostic::error(
+                    "E0108",
+                    "invalid left-hand side of assignment".to_string(),
+                )
+                .with_primary(left.span(), "cannot be assigned to")
+                .with_note("only variables can be assigned to"),
+            );
+            return None;
+        }
+
+        // Assignment is right-associative: `a = b = 3` assigns 3 to `b` and
+        // then the result to `a`, so the right-hand side may itself be an
+        // assignment.
         self.next_token();
-        self.parse_expression(precedence)
+        self.parse_expression(Precedence::Lowest)
             .map(|right| ast::Expression::AssignExpression {
+                span: left.span().to(right.span()),
                 left,
+                operator,
                 right: Box::new(right),
             })
     }
@@ -340,6 +549,7 @@ impl Parser {
         self.next_token();
         self.parse_expression(precedence)
             .map(|right| ast::Expression::InfixExpression {
+                span: left.span().to(right.span()),
                 left,
                 operator,
                 right: Box::new(right),
@@ -348,13 +558,24 @@ impl Parser {
 
     fn parse_call_expression(&mut self, function: ast::Expression) -> Option<ast::Expression> {
         match function {
-            ast::Expression::Identifier { value } => self
+            ast::Expression::Identifier { value, span } => self
                 .parse_expression_list(token::TokenType::RParen)
                 .map(|arguments| ast::Expression::CallExpression {
                     function: value,
                     arguments,
+                    span: span.to(self.cur_token.span),
                 }),
-            _ => None,
+            function => {
+                self.error(
+                    diagnostic::Diagnostic::error(
+                        "E0105",
+                        "only named functions can be called".to_string(),
+                    )
+                    .with_primary(function.span(), "not a function name"),
+                );
+
+                None
+            }
         }
     }
 
@@ -390,6 +611,7 @@ impl Parser {
     }
 
     fn parse_if_expression(&mut self) -> Option<ast::Expression> {
+        let span = self.cur_token.span;
         if !self.expect_peek(token::TokenType::LParen) {
             return None;
         }
@@ -409,38 +631,73 @@ impl Parser {
                         if self.peek_token_is(&token::TokenType::Else) {
                             self.next_token();
 
-                            if !self.expect_peek(token::TokenType::LBrace) {
-                                return None;
-                            }
-
-                            match self.parse_block_statement() {
+                            // `else if` makes the nested `if` the whole of the
+                            // alternative, and so also its value.
+                            let alternative = if self.peek_token_is(&token::TokenType::If) {
+                                self.next_token();
+                                self.parse_if_expression().map(|expression| {
+                                    ast::Statement::Expression {
+                                        span: expression.span(),
+                                        expression,
+                                        semicolon: false,
+                                    }
+                                })
+                            } else if self.expect_peek(token::TokenType::LBrace) {
+                                self.parse_block_statement()
+                            } else {
+                                None
+                            };
+
+                            match alternative {
                                 Some(alternative) => {
                                     let expression = ast::Expression::IfExpression {
+                                        span: span.to(alternative.span()),
                                         condition: Box::new(condition),
                                         consequence: Box::new(consequence),
                                         alternative: Some(Box::new(alternative)),
                                     };
                                     return Some(expression);
                                 }
-                                None => return Some(ast::Expression::NeedNext),
+                                None => return None,
                             }
                         }
 
                         let expression = ast::Expression::IfExpression {
+                            span: span.to(consequence.span()),
                             condition: Box::new(condition),
                             consequence: Box::new(consequence),
                             alternative: None,
                         };
                         Some(expression)
                     }
-                    None => Some(ast::Expression::NeedNext),
+                    None => None,
                 }
             }
             None => None,
         }
     }
 
-    fn parse_while_expression(&mut self) -> Option<ast::Expression> {
+    /// Parses a loop with a label, such as `'outer: while (...) { ... }`.
+    fn parse_labeled_expression(&mut self) -> Option<ast::Expression> {
+        let label = self.cur_token.literal.clone();
+        let span = self.cur_token.span;
+        if !self.expect_peek(token::TokenType::Colon) {
+            return None;
+        }
+        if !self.expect_peek(token::TokenType::While) {
+            return None;
+        }
+
+        self.parse_while_expression(Some(label), span)
+    }
+
+    /// Parses a `while` loop starting at its keyword. `span` is where the
+    /// loop starts, which is its label if it has one.
+    fn parse_while_expression(
+        &mut self,
+        label: Option<String>,
+        span: token::Span,
+    ) -> Option<ast::Expression> {
         if !self.expect_peek(token::TokenType::LParen) {
             return None;
         }
@@ -458,12 +715,14 @@ impl Parser {
                 match self.parse_block_statement() {
                     Some(consequence) => {
                         let expression = ast::Expression::WhileExpression {
+                            label,
+                            span: span.to(consequence.span()),
                             condition: Box::new(condition),
                             consequence: Box::new(consequence),
                         };
                         Some(expression)
                     }
-                    None => Some(ast::Expression::NeedNext),
+                    None => None,
                 }
             }
             None => None,
@@ -526,33 +785,90 @@ impl Parser {
     fn parse_identifier(&self) -> ast::Expression {
         ast::Expression::Identifier {
             value: self.cur_token.literal.clone(),
+            span: self.cur_token.span,
         }
     }
 
     fn parse_integer_literal(&mut self) -> Option<ast::Expression> {
-        if let Ok(value) = self.cur_token.literal.parse::<i64>() {
-            Some(ast::Expression::IntegerLiteral { value })
-        } else {
-            self.errors.push(format!(
-                "could not parse {} as integer",
-                self.cur_token.literal
-            ));
+        self.parse_integer_literal_with_limit(i64::MAX as u64)
+    }
 
-            None
+    /// Parses a decimal, `0x` hexadecimal, `0o` octal or `0b` binary literal
+    /// whose digits may be separated by `_`, and whose value is at most
+    /// `limit`.
+    fn parse_integer_literal_with_limit(&mut self, limit: u64) -> Option<ast::Expression> {
+        let literal = self.cur_token.literal.clone();
+        let span = self.cur_token.span;
+
+        let (radix, kind, digits) = match literal.get(..2) {
+            Some("0x") => (16, "hexadecimal", &literal[2..]),
+            Some("0o") => (8, "octal", &literal[2..]),
+            Some("0b") => (2, "binary", &literal[2..]),
+            _ => (10, "decimal", &literal[..]),
+        };
+        let digits: String = digits.chars().filter(|&c| c != '_').collect();
+
+        if digits.is_empty() {
+            self.error(
+                diagnostic::Diagnostic::error("E0107", format!("no digits in {} literal", kind))
+                    .with_primary(span, "expected at least one digit"),
+            );
+            return None;
+        }
+        if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+            self.error(
+                diagnostic::Diagnostic::error(
+                    "E0107",
+                    format!("invalid digit `{}` in {} literal", c, kind),
+                )
+                .with_primary(span, "invalid integer literal"),
+            );
+            return None;
+        }
+
+        match u64::from_str_radix(&digits, radix) {
+            Ok(value) if value <= limit => Some(ast::Expression::IntegerLiteral { value, span }),
+            _ => {
+                self.error(
+                    diagnostic::Diagnostic::error(
+                        "E0102",
+                        format!("integer literal `{}` is out of range", literal),
+                    )
+                    .with_primary(span, "does not fit in a signed 64-bit integer")
+                    .with_note(&format!(
+                        "integer literals must be between {} and {}",
+                        i64::MIN,
+                        i64::MAX
+                    )),
+                );
+
+                None
+            }
         }
     }
 
-    // fn parse_string_literal(&mut self) -> Option<ast::Expression> {
-    //     return Some(ast::Expression::StringLiteral {
-    //         value: self.cur_token.literal.clone(),
-    //     });
-    // }
+    fn parse_string_literal(&self) -> ast::Expression {
+        ast::Expression::StringLiteral {
+            value: self.cur_token.literal.clone(),
+            span: self.cur_token.span,
+        }
+    }
 
-    // fn parse_boolean(&mut self) -> ast::Expression {
-    //     return ast::Expression::Boolean {
-    //         value: self.cur_token_is(&token::TokenType::TRUE),
-    //     };
-    // }
+    fn parse_char_literal(&self) -> ast::Expression {
+        ast::Expression::CharLiteral {
+            // The literal is empty if the lexer has already reported it as
+            // invalid.
+            value: self.cur_token.literal.chars().next().unwrap_or('\0'),
+            span: self.cur_token.span,
+        }
+    }
+
+    fn parse_boolean(&self) -> ast::Expression {
+        ast::Expression::Boolean {
+            value: self.cur_token_is(&token::TokenType::True),
+            span: self.cur_token.span,
+        }
+    }
 
     fn parse_grouped_expression(&mut self) -> Option<ast::Expression> {
         self.next_token();
@@ -574,6 +890,20 @@ impl Parser {
     //     }
     // }
 
+    /// Records a syntax error unless one was already reported at the same
+    /// position, which keeps a single mistake from cascading.
+    fn error(&mut self, d: diagnostic::Diagnostic) {
+        let position = d.primary.as_ref().map(|label| label.span.start);
+        if let Some(last) = self.errors.last() {
+            if position.is_some() && last.primary.as_ref().map(|label| label.span.start) == position
+            {
+                return;
+            }
+        }
+
+        self.errors.push(d);
+    }
+
     fn cur_token_is(&self, t: &token::TokenType) -> bool {
         self.cur_token.token_type == *t
     }
@@ -592,11 +922,40 @@ impl Parser {
         }
     }
 
+    /// Like `expect_peek(Ident)`, but explains when the name is a keyword.
+    fn expect_ident(&mut self) -> bool {
+        if !self.peek_token.token_type.is_keyword() {
+            return self.expect_peek(token::TokenType::Ident);
+        }
+
+        let keyword = &self.peek_token.literal;
+        self.error(
+            diagnostic::Diagnostic::error(
+                "E0106",
+                format!("expected identifier, found keyword `{}`", keyword),
+            )
+            .with_primary(self.peek_token.span, "expected identifier, found keyword")
+            .with_note(&format!(
+                "`{}` is a reserved word and cannot be used as a name",
+                keyword
+            )),
+        );
+
+        false
+    }
+
     fn no_prefix_parse_fn_error(&mut self) {
-        self.errors.push(format!(
-            "no prefix parse function for {:?} found",
-            self.cur_token.token_type
-        ));
+        if self.cur_token_is(&token::TokenType::Illegal) {
+            return;
+        }
+
+        self.error(
+            diagnostic::Diagnostic::error(
+                "E0101",
+                format!("expected expression, found {}", self.cur_token.token_type),
+            )
+            .with_primary(self.cur_token.span, "expected expression"),
+        );
     }
 
     fn peek_precedence(&mut self) -> Precedence {
@@ -660,6 +1019,333 @@ mod lexer_tests {
         );
         assert_eq!(program.functions.len(), 2);
     }
+
+    #[test]
+    fn test_error_recovery() {
+        let input = "
+fn main() {
+    a = 1 +;
+    b = (2;
+    return a;
+}
+fn f( {
+}
+fn g() { return 1 }
+let
+fn h(a, 1) { x = ; y = 3 * ; }
+fn k() { (1)(2);
+";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+
+        let errors: Vec<(&str, usize, usize)> = p
+            .errors
+            .iter()
+            .map(|d| {
+                let span = d.primary.as_ref().unwrap().span;
+                (d.code, span.line, span.column)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0101", 3, 12),
+                ("E0100", 4, 11),
+                ("E0100", 7, 7),
+                ("E0103", 10, 1),
+                ("E0100", 11, 9),
+                ("E0105", 12, 11),
+                ("E0104", 13, 1),
+            ]
+        );
+
+        let names: Vec<&str> = program.functions.iter().map(|f| &*f.name).collect();
+        assert_eq!(names, vec!["main", "g"]);
+        assert_eq!(
+            program.functions[0].to_string(),
+            "fn main() {\r\n\treturn a;\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        let tests = [
+            ("0xFF", "255"),
+            ("0b1010_1010", "170"),
+            ("0o17", "15"),
+            ("1_000_000", "1000000"),
+            ("9223372036854775807", "9223372036854775807"),
+            ("-9223372036854775808", "(-9223372036854775808)"),
+            ("-0x8000_0000_0000_0000", "(-9223372036854775808)"),
+        ];
+        for (input, expected) in tests {
+            let l = lexer::Lexer::new(input);
+            let mut p = Parser::new(l);
+            let expression = p.parse_expression(Precedence::Lowest).unwrap();
+            assert!(p.errors.is_empty(), "{}", input);
+            assert_eq!(expression.to_string(), expected);
+        }
+
+        let tests = [
+            ("9223372036854775808", "E0102"),
+            ("-9223372036854775809", "E0102"),
+            ("-(9223372036854775808)", "E0102"),
+            ("99999999999999999999", "E0102"),
+            ("0x", "E0107"),
+            ("0b102", "E0107"),
+            ("12ab", "E0107"),
+        ];
+        for (input, code) in tests {
+            let l = lexer::Lexer::new(input);
+            let mut p = Parser::new(l);
+            assert!(p.parse_expression(Precedence::Lowest).is_none());
+            assert_eq!(p.errors.len(), 1, "{}", input);
+            assert_eq!(p.errors[0].code, code, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_keyword_as_name() {
+        let input = "fn while() {}\nfn f(a, if) {}\nfn fib_iter(_n, x1) { return _n + x1; }";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+
+        let errors: Vec<(&str, String, usize, usize)> = p
+            .errors
+            .iter()
+            .map(|d| {
+                let span = d.primary.as_ref().unwrap().span;
+                (d.code, d.message.clone(), span.line, span.column)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "E0106",
+                    "expected identifier, found keyword `while`".to_string(),
+                    1,
+                    4
+                ),
+                (
+                    "E0106",
+                    "expected identifier, found keyword `if`".to_string(),
+                    2,
+                    9
+                ),
+            ]
+        );
+
+        let names: Vec<&str> = program.functions.iter().map(|f| &*f.name).collect();
+        assert_eq!(names, vec!["fib_iter"]);
+        assert_eq!(program.functions[0].parameters, vec!["_n", "x1"]);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let tests = [
+            ("!-a", "(!(-a))"),
+            ("!a == b", "((!a) == b)"),
+            ("!(a == b)", "(!(a == b))"),
+            ("3 > 5 == false", "((3 > 5) == false)"),
+            ("!true + 1", "((!true) + 1)"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c && d", "((a && b) || (c && d))"),
+            ("a < b && !c == d", "((a < b) && ((!c) == d))"),
+            ("a || b || c", "((a || b) || c)"),
+            ("a % b * c", "((a % b) * c)"),
+            ("a + b << c - d", "((a + b) << (c - d))"),
+            ("a << b < c >> d", "((a << b) < (c >> d))"),
+            ("a & b == c", "(a & (b == c))"),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a && b | c", "(a && (b | c))"),
+            ("~a & ~-b", "((~a) & (~(-b)))"),
+            ("a += b * c", "a += (b * c)"),
+            ("a <<= b | c", "a <<= (b | c)"),
+            ("a >>= b >> c", "a >>= (b >> c)"),
+            ("a = b = c", "a = b = c"),
+            ("a = b += c || d", "a = b += (c || d)"),
+        ];
+
+        for (input, expected) in tests {
+            let l = lexer::Lexer::new(&format!("fn main() {{ {}; }}", input));
+            let mut p = Parser::new(l);
+            let program = p.parse_program();
+            assert!(p.errors.is_empty(), "{}", input);
+
+            let statement = match &program.functions[0].body {
+                ast::Statement::Block { statements, .. } => &statements[0],
+                _ => panic!("function body is not a block"),
+            };
+            assert_eq!(statement.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let input = "fn main() { let x = 5; let mut y = x + 1; let mut_z = 2; let mut = 3; }";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+
+        let codes: Vec<&str> = p.errors.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0100"]);
+
+        let statements = match &program.functions[0].body {
+            ast::Statement::Block { statements, .. } => statements,
+            _ => panic!("function body is not a block"),
+        };
+        let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            statements,
+            vec!["let x = 5", "let mut y = (x + 1)", "let mut_z = 2"]
+        );
+    }
+
+    #[test]
+    fn test_if_and_block_expressions() {
+        let input = "fn main() {
+            if (a) { 1 } else if (b) { 2 } else { 3 }
+            let x = { let y = 1; y + 1 } * 2;
+            { a } - 1;
+        }";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+
+        let statements = match &program.functions[0].body {
+            ast::Statement::Block { statements, .. } => statements,
+            _ => panic!("function body is not a block"),
+        };
+        let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "if (a) {\r\n\t1\r\n} else if (b) {\r\n\t2\r\n} else {\r\n\t3\r\n}",
+                "let x = ({\r\n\tlet y = 1;\r\n\t(y + 1)\r\n} * 2)",
+                "{\r\n\ta\r\n}",
+                "(-1)",
+            ]
+        );
+
+        match &program.functions[0].body.tail() {
+            None => {}
+            tail => panic!("unexpected tail expression {:?}", tail),
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let input = "fn main() {
+            'outer: while (a) { while (b) { break 'outer; } continue }
+            while (c) { break; continue 'outer; }
+        }";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+
+        let statements = match &program.functions[0].body {
+            ast::Statement::Block { statements, .. } => statements,
+            _ => panic!("function body is not a block"),
+        };
+        let span = statements[0].span();
+        assert_eq!(&input[span.start..span.start + 13], "'outer: while");
+
+        let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "'outer: while (a) {\r\n\twhile (b) {\r\n\tbreak 'outer;\r\n}\r\n\tcontinue;\r\n}",
+                "while (c) {\r\n\tbreak;\r\n\tcontinue 'outer;\r\n}",
+            ]
+        );
+
+        let l = lexer::Lexer::new("fn main() { 'outer while (a) {} }");
+        let mut p = Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors[0].message, "expected `:`, found `while`");
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let input = "fn main() {\n  1 = a;\n  f() = 2;\n  a = b + 1 = 3;\n  a = (b) = 4;\n}";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+
+        let errors: Vec<(&str, &str)> = p
+            .errors
+            .iter()
+            .map(|d| {
+                let span = d.primary.as_ref().unwrap().span;
+                (d.code, &input[span.start..span.end])
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![("E0108", "1"), ("E0108", "f()"), ("E0108", "b + 1")]
+        );
+
+        match &program.functions[0].body {
+            ast::Statement::Block { statements, .. } => {
+                assert_eq!(statements.len(), 1);
+                assert_eq!(statements[0].to_string(), "a = b = 4");
+            }
+            _ => panic!("function body is not a block"),
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "fn main() {\n  a = 1 + b;\n  return f(a);\n}";
+
+        let l = lexer::Lexer::new(input);
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+
+        let function = &program.functions[0];
+        assert_eq!(function.span, token::Span::new(0, 41, 1, 1));
+
+        let statements = match &function.body {
+            ast::Statement::Block { statements, span } => {
+                assert_eq!(*span, token::Span::new(10, 41, 1, 11));
+                statements
+            }
+            _ => panic!("function body is not a block"),
+        };
+
+        match &statements[0] {
+            ast::Statement::Expression {
+                expression: ast::Expression::AssignExpression { right, span, .. },
+                ..
+            } => {
+                assert_eq!(&input[span.start..span.end], "a = 1 + b");
+                assert_eq!(right.span(), token::Span::new(18, 23, 2, 7));
+            }
+            _ => panic!("statement is not an assignment"),
+        }
+
+        let span = statements[1].span();
+        assert_eq!(&input[span.start..span.end], "return f(a)");
+        assert_eq!((span.line, span.column), (3, 3));
+    }
 }
 
 // #[cfg(test)]
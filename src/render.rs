@@ -0,0 +1,244 @@
+use super::{diagnostic, source};
+
+/// Formats `d` for a terminal, quoting the offending lines from `sources`
+/// with `^` under the primary label and `-` under secondary labels. Labels
+/// on the same line share one quoted line, and a label in a different file
+/// from the one before it gets a header naming its file.
+pub fn render(d: &diagnostic::Diagnostic, sources: &source::SourceMap) -> String {
+    let mut out = format!("{}[{}]: {}\n", d.severity, d.code, d.message);
+
+    let mut labels = Vec::new();
+    if let Some(primary) = &d.primary {
+        labels.push((primary, '^'));
+    }
+    for secondary in &d.secondary {
+        labels.push((secondary, '-'));
+    }
+
+    let width = labels
+        .iter()
+        .map(|(label, _)| label.span.line.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    if d.primary.is_some() {
+        // Lines are quoted in the order their first label appears in.
+        let mut lines: Vec<(&source::SourceFile, usize, Vec<_>)> = Vec::new();
+        for (label, marker) in labels {
+            if let Some(file) = sources.lookup(label.span.start) {
+                match lines
+                    .iter_mut()
+                    .find(|(f, line, _)| f.offset == file.offset && *line == label.span.line)
+                {
+                    Some((_, _, line_labels)) => line_labels.push((label, marker)),
+                    None => lines.push((file, label.span.line, vec![(label, marker)])),
+                }
+            }
+        }
+
+        let mut previous = None;
+        for (file, _, line_labels) in lines {
+            if previous != Some(file.offset) {
+                let span = line_labels[0].0.span;
+                out += &format!(
+                    "{}--> {}:{}:{}\n",
+                    " ".repeat(width),
+                    file.name,
+                    span.line,
+                    span.column
+                );
+                out += &format!("{} |\n", " ".repeat(width));
+                previous = Some(file.offset);
+            }
+            render_line(&mut out, file, &line_labels, width);
+        }
+    }
+
+    for note in &d.notes {
+        out += &format!("{} = note: {}\n", " ".repeat(width), note);
+    }
+
+    out
+}
+
+/// Quotes the line of `labels`, which all lie on the same line of `file`.
+///
+/// Every label gets its markers on the line below the quote. The message of
+/// the rightmost label follows its markers, and the others hang below on
+/// `|` connectors, like rustc does.
+fn render_line(
+    out: &mut String,
+    file: &source::SourceFile,
+    labels: &[(&diagnostic::Label, char)],
+    width: usize,
+) {
+    let source = &file.text;
+    let first = clamp_to_boundary(source, labels[0].0.span.start - file.offset);
+    let line_start = source[..first].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[first..]
+        .find('\n')
+        .map_or(source.len(), |i| first + i);
+    let text = source[line_start..line_end].trim_end_matches('\r');
+
+    // (column, length, marker, message) of each label, in characters.
+    let mut marks: Vec<(usize, usize, char, &str)> = labels
+        .iter()
+        .map(|(label, marker)| {
+            let start = clamp_to_boundary(source, label.span.start - file.offset);
+            let end = clamp_to_boundary(source, (label.span.end - file.offset).max(start));
+            let column = source[line_start..start].chars().count();
+            let length = source[start..end.min(line_end)].chars().count().max(1);
+            (column, length, *marker, label.message.as_str())
+        })
+        .collect();
+    // The primary label is drawn last so that its markers win any overlap.
+    marks.sort_by_key(|&(column, _, marker, _)| (column, marker == '^'));
+
+    // Keep tabs so that the markers line up with the quoted text.
+    let blank = |length: usize| -> Vec<char> {
+        let mut chars: Vec<char> = text
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .take(length)
+            .collect();
+        chars.resize(length, ' ');
+        chars
+    };
+    let gutter = " ".repeat(width);
+
+    let marker_end = marks
+        .iter()
+        .map(|&(column, length, _, _)| column + length)
+        .max()
+        .unwrap_or(0);
+    let mut line = blank(marker_end);
+    for &(column, length, marker, _) in marks.iter().filter(|mark| mark.2 != '^') {
+        line[column..column + length].fill(marker);
+    }
+    for &(column, length, marker, _) in marks.iter().filter(|mark| mark.2 == '^') {
+        line[column..column + length].fill(marker);
+    }
+    let mut line: String = line.into_iter().collect();
+
+    let (_, _, _, message) = marks.pop().unwrap();
+    if !message.is_empty() {
+        line += " ";
+        line += message;
+    }
+    *out += &format!(
+        "{:>width$} | {}\n",
+        labels[0].0.span.line,
+        text,
+        width = width
+    );
+    *out += &format!("{} | {}\n", gutter, line);
+
+    marks.retain(|&(_, _, _, message)| !message.is_empty());
+    for i in (0..marks.len()).rev() {
+        let (column, _, _, message) = marks[i];
+        let mut connectors = blank(column + 1);
+        for &(left, _, _, _) in &marks[..=i] {
+            connectors[left] = '|';
+        }
+        let mut label = blank(column);
+        for &(left, _, _, _) in &marks[..i] {
+            label[left] = '|';
+        }
+
+        *out += &format!("{} | {}\n", gutter, String::from_iter(connectors));
+        *out += &format!("{} | {}{}\n", gutter, String::from_iter(label), message);
+    }
+}
+
+fn clamp_to_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::super::token;
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut sources = source::SourceMap::new();
+        sources.add("empty.rs".to_string(), String::new());
+        sources.add(
+            "test.rs".to_string(),
+            "fn main() {\n\treturn 1 +;\n}".to_string(),
+        );
+        let d = diagnostic::Diagnostic::error("E0101", "expected expression".to_string())
+            .with_primary(token::Span::new(24, 25, 2, 12), "expected expression")
+            .with_secondary(token::Span::new(1, 3, 1, 1), "in this function")
+            .with_note("operators need two operands");
+
+        assert_eq!(
+            render(&d, &sources),
+            "error[E0101]: expected expression\n \
+             --> test.rs:2:12\n  \
+             |\n\
+             2 | \treturn 1 +;\n  \
+             | \t          ^ expected expression\n\
+             1 | fn main() {\n  \
+             | -- in this function\n  \
+             = note: operators need two operands\n"
+        );
+    }
+
+    #[test]
+    fn test_render_same_line() {
+        let mut sources = source::SourceMap::new();
+        sources.add("test.rs".to_string(), "let x = 1; x = 2;".to_string());
+        let d = diagnostic::Diagnostic::error("E0208", "cannot assign twice".to_string())
+            .with_primary(token::Span::new(11, 16, 1, 12), "cannot assign twice")
+            .with_secondary(token::Span::new(4, 5, 1, 5), "declared immutable here")
+            .with_secondary(token::Span::new(0, 3, 1, 1), "");
+
+        assert_eq!(
+            render(&d, &sources),
+            "error[E0208]: cannot assign twice\n \
+             --> test.rs:1:12\n  \
+             |\n\
+             1 | let x = 1; x = 2;\n  \
+             | --- -      ^^^^^ cannot assign twice\n  \
+             |     |\n  \
+             |     declared immutable here\n"
+        );
+    }
+
+    #[test]
+    fn test_render_two_files() {
+        let mut sources = source::SourceMap::new();
+        sources.add("a.src".to_string(), "fn f() { 1 }".to_string());
+        sources.add(
+            "b.src".to_string(),
+            "fn main() { 0 }\nfn f() { 2 }".to_string(),
+        );
+        let d = diagnostic::Diagnostic::error("E0209", "defined more than once".to_string())
+            .with_primary(token::Span::new(29, 41, 2, 1), "redefined here")
+            .with_secondary(token::Span::new(0, 12, 1, 1), "first defined here")
+            .with_secondary(token::Span::new(13, 28, 1, 1), "");
+
+        assert_eq!(
+            render(&d, &sources),
+            "error[E0209]: defined more than once\n \
+             --> b.src:2:1\n  \
+             |\n\
+             2 | fn f() { 2 }\n  \
+             | ^^^^^^^^^^^^ redefined here\n \
+             --> a.src:1:1\n  \
+             |\n\
+             1 | fn f() { 1 }\n  \
+             | ------------ first defined here\n \
+             --> b.src:1:1\n  \
+             |\n\
+             1 | fn main() { 0 }\n  \
+             | ---------------\n"
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::{HashMap, HashSet};
+
+use super::compiler::CompileError;
+use super::{ast, runtime, token};
+
+/// Checks that every name in a program refers to something it can see, and
+/// that calls match the functions they call, before any code is generated.
+///
+/// Unlike the compiler, which stops a function at its first error, the
+/// resolver reports every problem it finds.
+pub struct Resolver {
+    /// Arity of every function the program defines, and where it is
+    /// defined. Any other function must be a builtin.
+    functions: HashMap<String, (usize, token::Span)>,
+    /// The locals declared in each enclosing scope, innermost last.
+    scopes: Vec<HashSet<String>>,
+    /// The label of each enclosing loop, innermost last.
+    loops: Vec<Option<String>>,
+    errors: Vec<CompileError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            functions: HashMap::new(),
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve_program(&mut self, program: &ast::Program) -> Result<(), Vec<CompileError>> {
+        self.functions = HashMap::new();
+        for function in &program.functions {
+            match self.functions.get(&function.name) {
+                Some((_, first)) => self.errors.push(CompileError::DuplicateFunction {
+                    name: function.name.clone(),
+                    first: *first,
+                    span: function.span,
+                }),
+                None => {
+                    self.functions.insert(
+                        function.name.clone(),
+                        (function.parameters.len(), function.span),
+                    );
+                }
+            }
+        }
+
+        if !program
+            .functions
+            .iter()
+            .any(|function| function.name == "main")
+        {
+            self.errors.push(CompileError::MissingMain);
+        }
+
+        for function in &program.functions {
+            self.scopes = vec![function.parameters.iter().cloned().collect()];
+            self.resolve_statement(&function.body);
+            self.check_return_paths(function);
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_statement(&mut self, stmt: &ast::Statement) {
+        match stmt {
+            ast::Statement::Let { name, value, .. } => {
+                self.resolve_expression(value);
+                self.scopes.last_mut().unwrap().insert(name.clone());
+            }
+            ast::Statement::Return { return_value, .. } => self.resolve_expression(return_value),
+            ast::Statement::Break { label, span } => {
+                self.resolve_loop_control("break", label.as_deref(), *span)
+            }
+            ast::Statement::Continue { label, span } => {
+                self.resolve_loop_control("continue", label.as_deref(), *span)
+            }
+            ast::Statement::Expression { expression, .. } => self.resolve_expression(expression),
+            ast::Statement::Block { statements, .. } => {
+                self.scopes.push(HashSet::new());
+                for stmt in statements {
+                    self.resolve_statement(stmt);
+                }
+                self.scopes.pop();
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, exp: &ast::Expression) {
+        match exp {
+            ast::Expression::Identifier { value, span } => {
+                if !self.is_declared(value) {
+                    self.errors.push(CompileError::UnknownVariable {
+                        name: value.clone(),
+                        span: *span,
+                    });
+                }
+            }
+            ast::Expression::IntegerLiteral { .. }
+            | ast::Expression::StringLiteral { .. }
+            | ast::Expression::CharLiteral { .. }
+            | ast::Expression::Boolean { .. } => {}
+            ast::Expression::PrefixExpression { right, .. } => self.resolve_expression(right),
+            ast::Expression::InfixExpression { left, right, .. } => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            ast::Expression::AssignExpression { left, right, .. } => {
+                match left.as_ref() {
+                    ast::Expression::Identifier { value, span } => {
+                        if !self.is_declared(value) {
+                            self.errors.push(CompileError::AssignToUndeclared {
+                                name: value.clone(),
+                                span: *span,
+                            });
+                        }
+                    }
+                    left => self
+                        .errors
+                        .push(CompileError::InvalidAssignmentTarget { span: left.span() }),
+                }
+                self.resolve_expression(right);
+            }
+            ast::Expression::IfExpression {
+                condition,
+                consequence,
+                alternative,
+                span,
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(consequence);
+                match alternative {
+                    Some(alternative) => self.resolve_statement(alternative),
+                    None if tail_has_value(consequence) => {
+                        self.errors.push(CompileError::MissingElse { span: *span })
+                    }
+                    None => {}
+                }
+            }
+            ast::Expression::WhileExpression {
+                label,
+                condition,
+                consequence,
+                ..
+            } => {
+                self.resolve_expression(condition);
+                self.loops.push(label.clone());
+                self.resolve_statement(consequence);
+                self.loops.pop();
+            }
+            ast::Expression::BlockExpression { body, .. } => self.resolve_statement(body),
+            ast::Expression::CallExpression {
+                function,
+                arguments,
+                span,
+            } => {
+                let arity = match self.functions.get(function) {
+                    Some(&(arity, _)) => Some(arity),
+                    None => runtime::lookup(function).map(|builtin| builtin.arity),
+                };
+                match arity {
+                    Some(expected) if expected != arguments.len() => {
+                        self.errors.push(CompileError::ArityMismatch {
+                            name: function.clone(),
+                            expected,
+                            found: arguments.len(),
+                            span: *span,
+                        })
+                    }
+                    Some(_) => {}
+                    None => self.errors.push(CompileError::UnknownFunction {
+                        name: function.clone(),
+                        span: *span,
+                    }),
+                }
+
+                for arg in arguments {
+                    self.resolve_expression(arg);
+                }
+            }
+        }
+    }
+
+    /// Checks that a `break` or `continue` is inside a loop, and that its
+    /// label, if any, names one of the loops around it.
+    fn resolve_loop_control(&mut self, keyword: &str, label: Option<&str>, span: token::Span) {
+        match label {
+            _ if self.loops.is_empty() => self.errors.push(CompileError::LoopControlOutsideLoop {
+                keyword: keyword.to_string(),
+                span,
+            }),
+            Some(label) if !self.loops.iter().any(|l| l.as_deref() == Some(label)) => {
+                self.errors.push(CompileError::UndeclaredLabel {
+                    label: label.to_string(),
+                    span,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    /// A function that returns a value anywhere must do so on every path. One
+    /// that never does is a unit function, which implicitly returns 0.
+    fn check_return_paths(&mut self, function: &ast::Function) {
+        let has_value = contains_return(&function.body) || tail_has_value(&function.body);
+        if has_value && !ends_with_value(&function.body) {
+            self.errors.push(CompileError::MissingReturn {
+                name: function.name.clone(),
+                span: function.body.span(),
+            });
+        }
+    }
+
+    fn is_declared(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+}
+
+/// Whether every path through `stmt` ends in a `return`.
+fn always_returns(stmt: &ast::Statement) -> bool {
+    match stmt {
+        ast::Statement::Return { .. } => true,
+        // Only a loop can be left with these, and a loop never counts as
+        // always returning.
+        ast::Statement::Break { .. } | ast::Statement::Continue { .. } => false,
+        ast::Statement::Block { statements, .. } => statements.iter().any(always_returns),
+        ast::Statement::Let { value, .. } => expression_always_returns(value),
+        ast::Statement::Expression { expression, .. } => expression_always_returns(expression),
+    }
+}
+
+/// Whether evaluating `exp` always runs into a `return`.
+fn expression_always_returns(exp: &ast::Expression) -> bool {
+    match exp {
+        ast::Expression::IfExpression {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            expression_always_returns(condition)
+                || alternative
+                    .as_deref()
+                    .is_some_and(|alt| always_returns(consequence) && always_returns(alt))
+        }
+        // The body of a loop may not run at all.
+        ast::Expression::WhileExpression { condition, .. } => expression_always_returns(condition),
+        ast::Expression::BlockExpression { body, .. } => always_returns(body),
+        ast::Expression::PrefixExpression { right, .. } => expression_always_returns(right),
+        ast::Expression::InfixExpression {
+            left,
+            operator,
+            right,
+            ..
+        } => {
+            // The right operand of `&&` and `||` may be skipped.
+            expression_always_returns(left)
+                || (operator != "&&" && operator != "||" && expression_always_returns(right))
+        }
+        ast::Expression::AssignExpression { right, .. } => expression_always_returns(right),
+        ast::Expression::CallExpression { arguments, .. } => {
+            arguments.iter().any(expression_always_returns)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the tail expression of `stmt` may give it a value on some path.
+/// A block whose tail is itself a block or `if` without a value has none.
+fn tail_has_value(stmt: &ast::Statement) -> bool {
+    match stmt.tail() {
+        Some(ast::Expression::IfExpression {
+            consequence,
+            alternative: Some(alternative),
+            ..
+        }) => tail_has_value(consequence) || tail_has_value(alternative),
+        Some(ast::Expression::BlockExpression { body, .. }) => tail_has_value(body),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Whether every path through `stmt` either returns or ends in a tail
+/// expression that has a value.
+fn ends_with_value(stmt: &ast::Statement) -> bool {
+    if always_returns(stmt) {
+        return true;
+    }
+
+    match stmt.tail() {
+        Some(ast::Expression::IfExpression {
+            consequence,
+            alternative: Some(alternative),
+            ..
+        }) => ends_with_value(consequence) && ends_with_value(alternative),
+        Some(ast::Expression::BlockExpression { body, .. }) => ends_with_value(body),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Whether `stmt` contains a `return` anywhere.
+fn contains_return(stmt: &ast::Statement) -> bool {
+    match stmt {
+        ast::Statement::Return { .. } => true,
+        ast::Statement::Break { .. } | ast::Statement::Continue { .. } => false,
+        ast::Statement::Block { statements, .. } => statements.iter().any(contains_return),
+        ast::Statement::Let { value, .. } => expression_contains_return(value),
+        ast::Statement::Expression { expression, .. } => expression_contains_return(expression),
+    }
+}
+
+fn expression_contains_return(exp: &ast::Expression) -> bool {
+    match exp {
+        ast::Expression::IfExpression {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            expression_contains_return(condition)
+                || contains_return(consequence)
+                || alternative.as_deref().is_some_and(contains_return)
+        }
+        ast::Expression::WhileExpression {
+            condition,
+            consequence,
+            ..
+        } => expression_contains_return(condition) || contains_return(consequence),
+        ast::Expression::BlockExpression { body, .. } => contains_return(body),
+        ast::Expression::PrefixExpression { right, .. } => expression_contains_return(right),
+        ast::Expression::InfixExpression { left, right, .. } => {
+            expression_contains_return(left) || expression_contains_return(right)
+        }
+        ast::Expression::AssignExpression { right, .. } => expression_contains_return(right),
+        ast::Expression::CallExpression { arguments, .. } => {
+            arguments.iter().any(expression_contains_return)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use super::super::{lexer, parser};
+    use super::*;
+
+    fn resolve(input: &str) -> Vec<CompileError> {
+        let l = lexer::Lexer::new(input);
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+
+        match Resolver::new().resolve_program(&program) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert!(resolve(
+            "fn print(s) { return s; }
+             fn f(a, b) { let c = a; { let a = c + b; print(a); } return a; }
+             fn main() { let x = 1; let x = x + f(x, 2); return g(x); }
+             fn g(y) { return y; }"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_undefined_names() {
+        let input = "fn main() {\n  { let a = 1; }\n  b = a;\n  return f(c, print());\n}";
+        let errors: Vec<(String, &str)> = resolve(input)
+            .iter()
+            .map(|err| {
+                let span = err.span().unwrap();
+                (err.to_string(), &input[span.start..span.end])
+            })
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("cannot assign to undeclared variable `b`".to_string(), "b"),
+                ("cannot find variable `a`".to_string(), "a"),
+                ("cannot find function `f`".to_string(), "f(c, print())"),
+                ("cannot find variable `c`".to_string(), "c"),
+                (
+                    "function `print` takes 1 argument but 0 were supplied".to_string(),
+                    "print()"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_return_paths() {
+        let missing = |input: &str| -> Vec<String> {
+            resolve(input)
+                .iter()
+                .filter_map(|err| match err {
+                    CompileError::MissingReturn { name, .. } => Some(name.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert!(missing(
+            "fn a(n) { if (n) { return 1; } else { { return 2; } } }
+             fn b(n) { if (n) { return 1; } n }
+             fn c(n) { if (n) { 1 } else { 2 } }
+             fn d(n) { let mut i = n; while (i) { i -= 1; } }
+             fn e(n) { return n; n; }
+             fn f(n) { let x = { return n; }; }
+             fn g(n) { if (n) { 1 } else if (n > 1) { return 2; } else { { 3 } } }
+             fn h(n) { { 1; } }
+             fn main() { 0 }"
+        )
+        .is_empty());
+        assert_eq!(
+            missing(
+                "fn a(n) { if (n) { return 1; } }
+                 fn b(n) { while (n) { return 1; } }
+                 fn c(n) { if (n) { return 1; } else { n; } }
+                 fn d(n) { if (n > 1) { return n; } n; }
+                 fn e(n) { if (n) { 1 } else { if (n) { 2 } else { n; } } }
+                 fn f(n) { if (n) { 1 } else if (n > 1) { 2 } }
+                 fn g(n) { let x = n || { return 1; }; }
+                 fn main() { return 0; }"
+            ),
+            vec!["a", "b", "c", "d", "e", "f", "g"]
+        );
+    }
+
+    #[test]
+    fn test_loop_control() {
+        assert!(resolve(
+            "fn main() {
+                 'outer: while (1) { while (1) { break 'outer; } { continue; } }
+                 0
+             }"
+        )
+        .is_empty());
+
+        let input = "fn main() {
+  break;
+  'a: while (1) { break 'b; }
+  continue 'a;
+  0
+}";
+        let errors: Vec<(String, &str)> = resolve(input)
+            .iter()
+            .map(|err| {
+                let span = err.span().unwrap();
+                (err.to_string(), &input[span.start..span.end])
+            })
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("`break` outside of a loop".to_string(), "break"),
+                ("use of undeclared label `'b`".to_string(), "break 'b"),
+                ("`continue` outside of a loop".to_string(), "continue 'a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_else() {
+        let input =
+            "fn main() {\n  if (1) { 2; }\n  let x = if (1) { { 3 } } else if (0) { 4 };\n  x\n}";
+        let errors: Vec<(String, &str)> = resolve(input)
+            .iter()
+            .map(|err| {
+                let span = err.span().unwrap();
+                (err.to_string(), &input[span.start..span.end])
+            })
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![(
+                "`if` may be missing an `else` clause".to_string(),
+                "if (0) { 4 }"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let input = "fn f() { return 0; }\nfn f(a) { return a; }";
+        let errors = resolve(input);
+
+        assert_eq!(
+            errors,
+            vec![
+                CompileError::DuplicateFunction {
+                    name: "f".to_string(),
+                    first: token::Span::new(0, 20, 1, 1),
+                    span: token::Span::new(21, 42, 2, 1),
+                },
+                CompileError::MissingMain,
+            ]
+        );
+        assert!(errors[1].to_diagnostic().primary.is_none());
+    }
+}
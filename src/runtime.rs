@@ -0,0 +1,41 @@
+/// A function that every program can call without defining it. A function
+/// of the same name defined by the program takes precedence.
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: usize,
+    /// The definition, following the same calling convention as compiled
+    /// functions and labelled with the name's `compiler::symbol`.
+    pub asm: &'static str,
+}
+
+pub const BUILTINS: [Builtin; 1] = [Builtin {
+    name: "print",
+    arity: 1,
+    asm: PRINT,
+}];
+
+/// `print(s)` writes the NUL-terminated string `s` to standard output and
+/// returns the number of bytes written.
+const PRINT: &str = "\
+_print:
+  push rbp
+  mov rbp, rsp
+  mov rsi, rcx
+  mov rdx, 0
+.Lprint_len:
+  cmp byte ptr [rsi+rdx], 0
+  je .Lprint_write
+  add rdx, 1
+  jmp .Lprint_len
+.Lprint_write:
+  mov rax, 1
+  mov rdi, 1
+  syscall
+  mov rsp, rbp
+  pop rbp
+  ret
+";
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
@@ -0,0 +1,37 @@
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// Byte offset of the start of this file in the shared span space.
+    pub offset: usize,
+}
+
+/// The source files of one compilation.
+///
+/// Each file is lexed with its own base offset, so a span alone identifies
+/// the file it came from.
+#[derive(Default)]
+pub struct SourceMap {
+    pub files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { files: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: String, text: String) -> &SourceFile {
+        // Leave a gap of one byte so that the end-of-file span of a file is
+        // not mistaken for the start of the next one.
+        let offset = match self.files.last() {
+            Some(file) => file.offset + file.text.len() + 1,
+            None => 0,
+        };
+        self.files.push(SourceFile { name, text, offset });
+
+        self.files.last().unwrap()
+    }
+
+    pub fn lookup(&self, offset: usize) -> Option<&SourceFile> {
+        self.files.iter().rev().find(|file| file.offset <= offset)
+    }
+}
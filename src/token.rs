@@ -1,30 +1,54 @@
 use std::fmt;
 
+use counted_array::counted_array;
+
 #[allow(dead_code)]
 #[derive(PartialOrd, PartialEq, Debug, Clone, Eq, Hash)]
 pub enum TokenType {
     Illegal,
     EoF,
 
-    Ident, // add, foobar, x, y, ...
-    Int,   // 1343456
-    // STRING, // "hello world"
-    Assign,   // =
-    Plus,     // +
-    Minus,    // -
-    Asterisk, // *
-    Slash,    // /
-    // BANG, // !
-    Lt,    // <
-    Gt,    // >
-    Eq,    // ==
-    NotEq, // !=
-    LtEq,  // <=
-    GtEq,  // >=
+    Ident,     // add, foobar, x, y, ...
+    Int,       // 1343456
+    String,    // "hello world"
+    Char,      // 'a'
+    Label,     // 'outer
+    Assign,    // =
+    Plus,      // +
+    Minus,     // -
+    Asterisk,  // *
+    Slash,     // /
+    Percent,   // %
+    Bang,      // !
+    Tilde,     // ~
+    Ampersand, // &
+    Pipe,      // |
+    Caret,     // ^
+    Shl,       // <<
+    Shr,       // >>
+    Lt,        // <
+    Gt,        // >
+    Eq,        // ==
+    NotEq,     // !=
+    LtEq,      // <=
+    GtEq,      // >=
+    And,       // &&
+    Or,        // ||
+
+    PlusAssign,      // +=
+    MinusAssign,     // -=
+    AsteriskAssign,  // *=
+    SlashAssign,     // /=
+    PercentAssign,   // %=
+    AmpersandAssign, // &=
+    PipeAssign,      // |=
+    CaretAssign,     // ^=
+    ShlAssign,       // <<=
+    ShrAssign,       // >>=
 
     Comma,     // ,
     SemiColon, // ;
-    // COLON, // :
+    Colon,     // :
     // DOT, // .
     LParen, // (
     RParen, // )
@@ -35,48 +59,202 @@ pub enum TokenType {
 
     //
     Function, // fn
-    // LET,     // let
-    // TRUE,   // true
-    // FALSE, // false
-    If,     // if
-    Else,   // else
-    Return, // return
-    While,  // while
+    Let,      // let
+    Mut,      // mut
+    True,     // true
+    False,    // false
+    If,       // if
+    Else,     // else
+    Return,   // return
+    While,    // while
+    Break,    // break
+    Continue, // continue
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenType::Illegal => "illegal token",
+            TokenType::EoF => "end of file",
+            TokenType::Ident => "identifier",
+            TokenType::Int => "integer literal",
+            TokenType::String => "string literal",
+            TokenType::Char => "character literal",
+            TokenType::Label => "label",
+            TokenType::Assign => "`=`",
+            TokenType::Plus => "`+`",
+            TokenType::Minus => "`-`",
+            TokenType::Asterisk => "`*`",
+            TokenType::Slash => "`/`",
+            TokenType::Percent => "`%`",
+            TokenType::Bang => "`!`",
+            TokenType::Tilde => "`~`",
+            TokenType::Ampersand => "`&`",
+            TokenType::Pipe => "`|`",
+            TokenType::Caret => "`^`",
+            TokenType::Shl => "`<<`",
+            TokenType::Shr => "`>>`",
+            TokenType::Lt => "`<`",
+            TokenType::Gt => "`>`",
+            TokenType::Eq => "`==`",
+            TokenType::NotEq => "`!=`",
+            TokenType::LtEq => "`<=`",
+            TokenType::GtEq => "`>=`",
+            TokenType::And => "`&&`",
+            TokenType::Or => "`||`",
+            TokenType::PlusAssign => "`+=`",
+            TokenType::MinusAssign => "`-=`",
+            TokenType::AsteriskAssign => "`*=`",
+            TokenType::SlashAssign => "`/=`",
+            TokenType::PercentAssign => "`%=`",
+            TokenType::AmpersandAssign => "`&=`",
+            TokenType::PipeAssign => "`|=`",
+            TokenType::CaretAssign => "`^=`",
+            TokenType::ShlAssign => "`<<=`",
+            TokenType::ShrAssign => "`>>=`",
+            TokenType::Comma => "`,`",
+            TokenType::SemiColon => "`;`",
+            TokenType::Colon => "`:`",
+            TokenType::LParen => "`(`",
+            TokenType::RParen => "`)`",
+            TokenType::LBrace => "`{`",
+            TokenType::RBrace => "`}`",
+            TokenType::Function => "`fn`",
+            TokenType::Let => "`let`",
+            TokenType::Mut => "`mut`",
+            TokenType::True => "`true`",
+            TokenType::False => "`false`",
+            TokenType::If => "`if`",
+            TokenType::Else => "`else`",
+            TokenType::Return => "`return`",
+            TokenType::While => "`while`",
+            TokenType::Break => "`break`",
+            TokenType::Continue => "`continue`",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// A region of the source text.
+///
+/// `start` and `end` are byte offsets (`end` is exclusive); `line` and `column`
+/// are 1-based and describe where `start` lies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Returns a span covering `self` through the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    LineComment,
+    BlockComment,
+}
+
+/// Source text that carries no meaning for the parser but that tools such as
+/// a formatter need to preserve.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    /// The full comment, including its delimiters.
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    pub span: Span,
+    /// Comments between the previous token and this one.
+    pub leading_trivia: Vec<Trivia>,
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "token_type: {:?}, literal: {}",
-            self.token_type, self.literal
+            "token_type: {:?}, literal: {}, span: {}",
+            self.token_type, self.literal, self.span
         )
     }
 }
 
-pub fn lookup_ident(ident: &str) -> TokenType {
-    match ident {
-        "fn" => TokenType::Function,
-        // "let" => TokenType::LET,
-        // "true" => TokenType::TRUE,
-        // "false" => TokenType::FALSE,
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        "return" => TokenType::Return,
-        "while" => TokenType::While,
-        _ => TokenType::Ident,
+// Reserved words, which cannot be used as names.
+counted_array!(
+    pub const KEYWORDS: [(&str, TokenType); _] = [
+        ("fn", TokenType::Function),
+        ("let", TokenType::Let),
+        ("mut", TokenType::Mut),
+        ("true", TokenType::True),
+        ("false", TokenType::False),
+        ("if", TokenType::If),
+        ("else", TokenType::Else),
+        ("return", TokenType::Return),
+        ("while", TokenType::While),
+        ("break", TokenType::Break),
+        ("continue", TokenType::Continue),
+    ]
+);
+
+impl TokenType {
+    pub fn is_keyword(&self) -> bool {
+        KEYWORDS.iter().any(|(_, t)| t == self)
     }
 }
 
-pub fn new_token(token_type: TokenType, literal: String) -> Token {
+pub fn lookup_ident(ident: &str) -> TokenType {
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == ident)
+        .map_or(TokenType::Ident, |(_, t)| t.clone())
+}
+
+/// Whether `ch` can start an identifier.
+pub fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+/// Whether `ch` can appear in an identifier after the first character.
+pub fn is_ident_continue(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+pub fn new_token(token_type: TokenType, literal: String, span: Span) -> Token {
     Token {
         token_type,
         literal,
+        span,
+        leading_trivia: Vec::new(),
     }
 }